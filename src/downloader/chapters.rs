@@ -0,0 +1,388 @@
+use super::chat::{timestamp, vtt_escape};
+use super::utils::{verified, write_file};
+use crate::Error;
+use fancy_regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// A `h:mm:ss` or `m:ss` timestamp, optionally in brackets, not part of a longer number.
+static TIMESTAMP_REGEX: LazyLock<Regex> = LazyLock::new(|| unsafe {
+    Regex::new(r"(?<![\d:])[\[(]?((?:\d+:)?\d{1,2}:\d{2})[\])]?(?![\d:])").unwrap_unchecked()
+});
+
+/// A chapter of a video, kept in the `chapters` array of `{id}.json`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct Chapter {
+    /// Seconds from the start of the video
+    pub(crate) start: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) end: Option<f64>,
+    pub(crate) title: String,
+    /// What the chapter was derived from, e.g. `chat` for chat spikes
+    pub(crate) source: String,
+}
+
+/// Replaces the chapters from `source` in the video metadata at `path`,
+/// keeping the chapters from other sources and sorting them by start.
+pub(crate) fn merge(path: &Path, source: &str, chapters: Vec<Chapter>) -> Result<(), Error> {
+    let bytes = fs::read(path).map_err(|err| Error::io(err, path))?;
+    let mut metadata: Map<String, Value> =
+        serde_json::from_slice(&bytes).map_err(|err| Error::json(err, path))?;
+    let mut merged: Vec<Chapter> = match metadata.remove("chapters") {
+        Some(chapters) => serde_json::from_value(chapters).map_err(|err| Error::json(err, path))?,
+        None => Vec::new(),
+    };
+    merged.retain(|chapter| chapter.source != source);
+    merged.extend(chapters);
+    merged.sort_by(|a, b| a.start.total_cmp(&b.start));
+    metadata.insert("chapters".to_string(), serde_json::to_value(merged)?);
+    let mut json = serde_json::to_string_pretty(&metadata)?;
+    json.push('\n');
+    write_file(path, json.as_bytes())
+}
+
+/// Reads the chapters of the video metadata at `path`, none when it does not exist.
+pub(crate) fn read(path: &Path) -> Result<Vec<Chapter>, Error> {
+    if !verified(path) {
+        return Ok(Vec::new());
+    }
+    let bytes = fs::read(path).map_err(|err| Error::io(err, path))?;
+    let metadata: Map<String, Value> =
+        serde_json::from_slice(&bytes).map_err(|err| Error::json(err, path))?;
+    match metadata.get("chapters") {
+        Some(chapters) => {
+            serde_json::from_value(chapters.clone()).map_err(|err| Error::json(err, path))
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Chapters listed as lines starting or ending with a timestamp in a video
+/// description. Like on YouTube, the list has to start at `0:00` and have at
+/// least three ascending timestamps, otherwise there are none.
+pub(crate) fn from_description(description: &str, duration: Option<f64>) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+    for line in description.lines() {
+        let Ok(Some(captures)) = TIMESTAMP_REGEX.captures(line) else {
+            continue;
+        };
+        let (Some(matched), Some(time)) = (captures.get(0), captures.get(1)) else {
+            continue;
+        };
+        let start = time
+            .as_str()
+            .split(':')
+            .filter_map(|part| part.parse::<f64>().ok())
+            .fold(0.0, |seconds, part| seconds * 60.0 + part);
+        let separator = |char: char| char.is_whitespace() || "-–—|:•".contains(char);
+        let (before, after) = (
+            line[..matched.start()].trim_matches(separator),
+            line[matched.end()..].trim_matches(separator),
+        );
+        let title = match (before.is_empty(), after.is_empty()) {
+            (true, false) => after,
+            (false, true) => before,
+            _ => continue,
+        };
+        match chapters.last() {
+            None if start != 0.0 => continue,
+            Some(last) if start <= last.start => break,
+            _ => {}
+        }
+        if let Some(last) = chapters.last_mut() {
+            last.end = Some(start);
+        }
+        chapters.push(Chapter {
+            start,
+            end: duration.filter(|duration| *duration > start),
+            title: title.to_string(),
+            source: "description".to_string(),
+        });
+    }
+    if chapters.len() < 3 {
+        return Vec::new();
+    }
+    chapters
+}
+
+/// Splits a video into ranges titled after the latest chapter that started and
+/// has not ended, marking the ranges within `muted`. Without chapters, the
+/// ranges alternate between audio and muted audio.
+pub(crate) fn timeline(
+    chapters: &[Chapter],
+    muted: &[(f64, f64)],
+    duration: Option<f64>,
+) -> Vec<(f64, f64, String)> {
+    let mut points = vec![0.0];
+    points.extend(
+        chapters
+            .iter()
+            .flat_map(|chapter| [Some(chapter.start), chapter.end])
+            .flatten(),
+    );
+    points.extend(muted.iter().flat_map(|(start, end)| [*start, *end]));
+    points.extend(duration);
+    points.retain(|point| duration.is_none_or(|duration| *point <= duration));
+    points.sort_by(f64::total_cmp);
+    points.dedup();
+    let mut ranges: Vec<(f64, f64, String)> = Vec::new();
+    for (index, &start) in points.iter().enumerate() {
+        let end = match points.get(index + 1) {
+            Some(end) => *end,
+            // The last chapter is open ended when the duration is unknown
+            None if duration.is_none() => start,
+            None => break,
+        };
+        let chapter = chapters
+            .iter()
+            .filter(|chapter| chapter.start <= start && chapter.end.is_none_or(|end| end > start))
+            .max_by(|a, b| a.start.total_cmp(&b.start));
+        if chapter.is_none() && end <= start {
+            continue;
+        }
+        let is_muted = muted
+            .iter()
+            .any(|(muted, until)| *muted <= start && start < *until);
+        let title = match (chapter, is_muted) {
+            (Some(chapter), true) => format!("{} (muted audio)", chapter.title),
+            (Some(chapter), false) => chapter.title.clone(),
+            (None, true) => "Muted audio".to_string(),
+            (None, false) if !muted.is_empty() => "Audio".to_string(),
+            (None, false) => continue,
+        };
+        match ranges.last_mut() {
+            Some(last) if last.2 == title && last.1 == start => last.1 = end,
+            _ => ranges.push((start, end, title)),
+        }
+    }
+    ranges
+}
+
+/// Global `tags` and chapters in FFmpeg's metadata format.
+pub(crate) fn ffmetadata(
+    tags: &[(&str, &str)],
+    ranges: &[(f64, f64, String)],
+) -> Result<String, Error> {
+    let mut metadata = ";FFMETADATA1\n".to_string();
+    for (key, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+        writeln!(metadata, "{key}={}", escape(value))?;
+    }
+    for (start, end, title) in ranges {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (start, end) = ((start * 1000.0) as u64, (end * 1000.0) as u64);
+        write!(
+            metadata,
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={start}\nEND={end}\ntitle={}\n",
+            escape(title)
+        )?;
+    }
+    Ok(metadata)
+}
+
+/// Chapters as WebVTT cues, titled by their text.
+pub(crate) fn webvtt(ranges: &[(f64, f64, String)]) -> Result<String, Error> {
+    let mut vtt = String::from("WEBVTT\n");
+    for (number, (start, end, title)) in ranges.iter().enumerate() {
+        write!(
+            vtt,
+            "\n{}\n{} --> {}\n{}\n",
+            number + 1,
+            timestamp(*start, ".", 3, true),
+            timestamp(*end, ".", 3, true),
+            vtt_escape(title)
+        )?;
+    }
+    Ok(vtt)
+}
+
+/// Writes `{id}.chapters.ffmetadata` and `{id}.chapters.vtt` beside the
+/// video, replacing or removing the ones written before.
+pub(crate) fn sidecars(
+    dir: &Path,
+    id: &str,
+    chapters: &[Chapter],
+    duration: Option<f64>,
+) -> Result<(), Error> {
+    let ranges = timeline(chapters, &[], duration);
+    for (ext, contents) in [
+        ("ffmetadata", ffmetadata(&[], &ranges)?),
+        ("vtt", webvtt(&ranges)?),
+    ] {
+        let path = dir.join(format!("{id}.chapters.{ext}"));
+        match ranges.is_empty() {
+            true if path.exists() => fs::remove_file(&path).map_err(|err| Error::io(err, &path))?,
+            true => {}
+            false => write_file(&path, contents.as_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+/// Seconds in a duration like Twitch's `3h2m1s`, YouTube's `PT3H2M1S`, or `61.5`,
+/// none when it is not one or is negative.
+pub(crate) fn seconds(duration: &str) -> Option<f64> {
+    if let Ok(seconds) = duration.parse::<f64>() {
+        return (seconds.is_finite() && seconds >= 0.0).then_some(seconds);
+    }
+    let duration = duration.to_lowercase();
+    let duration = duration
+        .strip_prefix('p')
+        .unwrap_or(&duration)
+        .replace('t', "");
+    if duration.is_empty() {
+        return None;
+    }
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    for char in duration.chars() {
+        let unit = match char {
+            '0'..='9' | '.' => {
+                number.push(char);
+                continue;
+            }
+            'w' => 604_800.0,
+            'd' => 86_400.0,
+            'h' => 3_600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        seconds += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+    // A number without a unit is not a duration
+    number.is_empty().then_some(seconds)
+}
+
+/// Escapes the characters FFmpeg's metadata format gives a meaning to.
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for char in value.chars() {
+        if matches!(char, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds_of_twitch_and_iso_durations() {
+        let cases = [
+            ("1h2m3s", Some(3723.0)),
+            ("45s", Some(45.0)),
+            ("1w1d", Some(691_200.0)),
+            ("PT1H2M3S", Some(3723.0)),
+            ("PT1H2M", Some(3720.0)),
+            ("P1DT1S", Some(86_401.0)),
+            ("61.5", Some(61.5)),
+            ("0", Some(0.0)),
+        ];
+        for (duration, expected) in cases {
+            assert_eq!(seconds(duration), expected, "{duration}");
+        }
+    }
+
+    #[test]
+    fn seconds_rejects_invalid_durations() {
+        for duration in [
+            "", "PT", "nan", "inf", "-5", "PT1H2M3", "1h30", "1x", "h", "1..5s",
+        ] {
+            assert_eq!(seconds(duration), None, "{duration}");
+        }
+    }
+
+    fn chapters(description: &str, duration: Option<f64>) -> Vec<(f64, Option<f64>, String)> {
+        from_description(description, duration)
+            .into_iter()
+            .map(|chapter| (chapter.start, chapter.end, chapter.title))
+            .collect()
+    }
+
+    #[test]
+    fn description_chapters() {
+        let description = "Links below\n\
+                           0:00 Intro\n\
+                           1:30 - Part one\n\
+                           Watch 1:45 for the best part\n\
+                           [1:02:03] Finale\n\
+                           Outro | 1:05:00\n\
+                           Thanks for watching";
+        assert_eq!(
+            chapters(description, Some(4000.0)),
+            [
+                (0.0, Some(90.0), "Intro".to_string()),
+                (90.0, Some(3723.0), "Part one".to_string()),
+                (3723.0, Some(3900.0), "Finale".to_string()),
+                (3900.0, Some(4000.0), "Outro".to_string()),
+            ]
+        );
+        assert_eq!(
+            chapters("0:00 A\n1:00 B\n2:00 C", None).last(),
+            Some(&(120.0, None, "C".to_string()))
+        );
+    }
+
+    #[test]
+    fn description_chapters_follow_youtube_rules() {
+        let cases = [
+            // Not starting at 0:00
+            ("0:10 A\n1:00 B\n2:00 C", 0),
+            // Fewer than three
+            ("0:00 A\n1:00 B", 0),
+            // Out of order before the third
+            ("0:00 A\n2:00 B\n1:00 C\n3:00 D", 0),
+            // Out of order after the third, which ends the list
+            ("0:00 A\n1:00 B\n2:00 C\n1:30 D\n3:00 E", 3),
+            // Repeated timestamps are not ascending
+            ("0:00 A\n0:00 B\n1:00 C\n2:00 D", 0),
+        ];
+        for (description, expected) in cases {
+            assert_eq!(
+                from_description(description, None).len(),
+                expected,
+                "{description}"
+            );
+        }
+    }
+
+    #[test]
+    fn ffmetadata_escapes_values() {
+        let ranges = [
+            (0.0, 1.5, "One = two".to_string()),
+            (1.5, 3.0, "x;y\nz".to_string()),
+        ];
+        let tags = [("title", "a=b;c#d\\e\nf"), ("artist", "")];
+        assert_eq!(
+            ffmetadata(&tags, &ranges).unwrap(),
+            ";FFMETADATA1\n\
+             title=a\\=b\\;c\\#d\\\\e\\\nf\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1500\ntitle=One \\= two\n\
+             \n[CHAPTER]\nTIMEBASE=1/1000\nSTART=1500\nEND=3000\ntitle=x\\;y\\\nz\n"
+        );
+        assert_eq!(ffmetadata(&[], &[]).unwrap(), ";FFMETADATA1\n");
+    }
+
+    #[test]
+    fn webvtt_cues() {
+        let ranges = [
+            (0.0, 90.0, "Intro".to_string()),
+            (90.0, 3723.5, "Q&A <live>\nend".to_string()),
+        ];
+        assert_eq!(
+            webvtt(&ranges).unwrap(),
+            "WEBVTT\n\
+             \n1\n00:00:00.000 --> 00:01:30.000\nIntro\n\
+             \n2\n00:01:30.000 --> 01:02:03.500\nQ&amp;A &lt;live&gt; end\n"
+        );
+        assert_eq!(webvtt(&[]).unwrap(), "WEBVTT\n");
+    }
+}
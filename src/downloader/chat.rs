@@ -0,0 +1,892 @@
+use super::compress::{self, Codec};
+use super::utils::{colorize, error, write_file};
+use super::Context;
+use super::{html, stats};
+use crate::init::{ChatCommand, ChatTask};
+use crate::report;
+use crate::Error;
+use colored::Color;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter, Write};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// Formats rendered from every downloaded chat
+    pub(crate) formats: Vec<Format>,
+    /// Write a browsable `{id}.chat.html` page for every chat
+    pub(crate) html: bool,
+    /// Seconds each message stays on screen in subtitles
+    pub(crate) duration: f64,
+    /// Font used by SSA subtitles
+    pub(crate) font: String,
+    pub(crate) font_size: u32,
+    /// Numpad position of the SSA chat, 7 is the top left corner
+    pub(crate) alignment: u8,
+    pub(crate) margin_x: u32,
+    pub(crate) margin_y: u32,
+    /// Resolution the SSA position and font size are relative to
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            formats: vec![Format::Ssa],
+            html: false,
+            duration: 5.0,
+            font: "Arial".to_string(),
+            font_size: 36,
+            alignment: 7,
+            margin_x: 20,
+            margin_y: 20,
+            width: 1920,
+            height: 1080,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+    Ssa,
+    Srt,
+    Vtt,
+    Txt,
+}
+
+impl Format {
+    pub(crate) fn filename(self, id: &str) -> String {
+        format!("{id}.{self}")
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "ssa" => Ok(Format::Ssa),
+            "srt" => Ok(Format::Srt),
+            "vtt" => Ok(Format::Vtt),
+            "txt" => Ok(Format::Txt),
+            _ => Err(Error::Config(format!("Unknown chat format: {format}"))),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Format::Ssa => write!(f, "ssa"),
+            Format::Srt => write!(f, "srt"),
+            Format::Vtt => write!(f, "vtt"),
+            Format::Txt => write!(f, "txt"),
+        }
+    }
+}
+
+/// A chat message reduced to what the renderers need.
+#[derive(Debug)]
+pub(crate) struct Line {
+    pub(crate) offset: f64,
+    pub(crate) name: String,
+    pub(crate) color: Option<String>,
+    pub(crate) text: String,
+    /// Codes of the emotes the platform marked in the message
+    pub(crate) emotes: Vec<String>,
+}
+
+/// Chat read from any of the supported JSON formats.
+#[derive(Debug)]
+pub(crate) struct Chat {
+    pub(crate) title: String,
+    pub(crate) lines: Vec<Line>,
+}
+
+/// Chat in the `.chat.json` schema written by TwitchDownloaderCLI.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct ChatRoot {
+    #[serde(rename = "FileInfo", default)]
+    pub(crate) file_info: FileInfo,
+    #[serde(default)]
+    pub(crate) streamer: Streamer,
+    #[serde(default)]
+    pub(crate) video: ChatVideo,
+    #[serde(default)]
+    pub(crate) comments: Vec<Comment>,
+    #[serde(rename = "embeddedData", default)]
+    pub(crate) embedded_data: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct FileInfo {
+    #[serde(rename = "Version")]
+    pub(crate) version: Version,
+    #[serde(rename = "CreatedAt")]
+    pub(crate) created_at: String,
+    #[serde(rename = "UpdatedAt")]
+    pub(crate) updated_at: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Version {
+    #[serde(rename = "Major")]
+    pub(crate) major: u32,
+    #[serde(rename = "Minor")]
+    pub(crate) minor: u32,
+    #[serde(rename = "Patch")]
+    pub(crate) patch: u32,
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Self {
+            major: 1,
+            minor: 4,
+            patch: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Streamer {
+    pub(crate) name: String,
+    pub(crate) id: u64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct ChatVideo {
+    #[serde(default)]
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) description: Option<String>,
+    #[serde(default)]
+    pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) created_at: String,
+    #[serde(default)]
+    pub(crate) start: f64,
+    #[serde(default)]
+    pub(crate) end: f64,
+    #[serde(default)]
+    pub(crate) length: f64,
+    #[serde(rename = "viewCount", default)]
+    pub(crate) view_count: u64,
+    #[serde(default)]
+    pub(crate) game: Option<String>,
+    #[serde(default)]
+    pub(crate) chapters: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Comment {
+    #[serde(rename = "_id")]
+    pub(crate) id: String,
+    pub(crate) created_at: String,
+    #[serde(default)]
+    pub(crate) channel_id: String,
+    #[serde(default)]
+    pub(crate) content_type: String,
+    #[serde(default)]
+    pub(crate) content_id: String,
+    pub(crate) content_offset_seconds: f64,
+    pub(crate) commenter: Commenter,
+    pub(crate) message: Message,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Commenter {
+    pub(crate) display_name: String,
+    #[serde(rename = "_id")]
+    pub(crate) id: String,
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) bio: Option<String>,
+    #[serde(default)]
+    pub(crate) created_at: Option<String>,
+    #[serde(default)]
+    pub(crate) updated_at: Option<String>,
+    #[serde(default)]
+    pub(crate) logo: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Message {
+    pub(crate) body: String,
+    /// Left out when unknown, as the GQL comments do not carry it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) bits_spent: Option<u64>,
+    #[serde(default)]
+    pub(crate) fragments: Vec<Fragment>,
+    #[serde(default)]
+    pub(crate) user_badges: Vec<Badge>,
+    #[serde(default)]
+    pub(crate) user_color: Option<String>,
+    #[serde(default)]
+    pub(crate) emoticons: Vec<Emoticon>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Fragment {
+    pub(crate) text: String,
+    pub(crate) emoticon: Option<FragmentEmoticon>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct FragmentEmoticon {
+    pub(crate) emoticon_id: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Badge {
+    #[serde(rename = "_id")]
+    pub(crate) id: String,
+    pub(crate) version: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Emoticon {
+    #[serde(rename = "_id")]
+    pub(crate) id: String,
+    pub(crate) begin: usize,
+    pub(crate) end: usize,
+}
+
+/// A message in the list written by chat_downloader.
+#[derive(Debug, Deserialize)]
+struct CdMessage {
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    time_in_seconds: Option<f64>,
+    /// Microseconds since the epoch
+    #[serde(default)]
+    timestamp: Option<i64>,
+    #[serde(default)]
+    author: CdAuthor,
+    #[serde(default)]
+    emotes: Vec<CdEmote>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CdAuthor {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    colour: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CdEmote {
+    name: String,
+}
+
+pub(crate) fn write(path: impl AsRef<Path>, root: &ChatRoot) -> Result<(), Error> {
+    let json = serde_json::to_string(root)?;
+    write_file(path, json.as_bytes())
+}
+
+impl Chat {
+    /// Reads a TwitchDownloaderCLI or chat_downloader JSON file, or SSA subtitles.
+    pub(crate) fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if compress::strip(path)
+            .extension()
+            .is_some_and(|ext| ext == "ssa" || ext == "ass")
+        {
+            let mut ssa = String::new();
+            compress::open(path)?
+                .read_to_string(&mut ssa)
+                .map_err(|err| Error::io(err, path))?;
+            return Ok(Self::from_ssa(name(path), &ssa));
+        }
+        let value: serde_json::Value =
+            serde_json::from_reader(compress::open(path)?).map_err(|err| Error::json(err, path))?;
+        let chat = match value {
+            serde_json::Value::Array(_) => {
+                let messages: Vec<CdMessage> =
+                    serde_json::from_value(value).map_err(|err| Error::json(err, path))?;
+                Self::from_cd(name(path), messages)
+            }
+            value => {
+                let root: ChatRoot =
+                    serde_json::from_value(value).map_err(|err| Error::json(err, path))?;
+                Self::from(root)
+            }
+        };
+        Ok(chat)
+    }
+
+    /// Parses the `Dialogue` events of subtitles whose text is `user: message`.
+    fn from_ssa(name: String, ssa: &str) -> Self {
+        let mut title = name;
+        let mut lines = Vec::new();
+        for row in ssa.lines() {
+            if let Some(value) = row.strip_prefix("Title:").map(str::trim) {
+                if !value.is_empty() {
+                    title = value.to_string();
+                }
+                continue;
+            }
+            let Some(event) = row.strip_prefix("Dialogue:") else {
+                continue;
+            };
+            let fields: Vec<&str> = event.splitn(10, ',').collect();
+            let (Some(start), Some(text)) = (fields.get(1), fields.get(9)) else {
+                continue;
+            };
+            let Some(offset) = seconds(start.trim()) else {
+                continue;
+            };
+            let text = ssa_unescape(text);
+            let Some((name, text)) = text.split_once(": ") else {
+                continue;
+            };
+            lines.push(Line {
+                offset,
+                name: name.to_string(),
+                color: None,
+                text: text.to_string(),
+                emotes: Vec::new(),
+            });
+        }
+        Self { title, lines }
+    }
+
+    fn from_cd(title: String, messages: Vec<CdMessage>) -> Self {
+        let first = messages.iter().find_map(|message| message.timestamp);
+        let lines = messages
+            .into_iter()
+            .filter_map(|message| {
+                #[allow(clippy::cast_precision_loss)]
+                let offset = message
+                    .time_in_seconds
+                    .or_else(|| Some((message.timestamp? - first?) as f64 / 1_000_000.0))?;
+                Some(Line {
+                    offset,
+                    name: message.author.display_name.unwrap_or(message.author.name),
+                    color: message.author.colour,
+                    text: message.message.filter(|text| !text.is_empty())?,
+                    emotes: message.emotes.into_iter().map(|emote| emote.name).collect(),
+                })
+            })
+            .collect();
+        Self { title, lines }
+    }
+
+    pub(crate) fn render(&self, format: Format, settings: &Settings) -> Result<String, Error> {
+        match format {
+            Format::Ssa => self.ssa(settings),
+            Format::Srt => self.srt(settings),
+            Format::Vtt => self.vtt(settings),
+            Format::Txt => self.txt(),
+        }
+    }
+
+    fn ssa(&self, settings: &Settings) -> Result<String, Error> {
+        let mut ssa = String::new();
+        writeln!(ssa, "[Script Info]")?;
+        writeln!(ssa, "Title: {}", self.title.replace('\n', " "))?;
+        writeln!(ssa, "ScriptType: v4.00+")?;
+        writeln!(ssa, "PlayResX: {}", settings.width)?;
+        writeln!(ssa, "PlayResY: {}", settings.height)?;
+        writeln!(ssa)?;
+        writeln!(ssa, "[V4+ Styles]")?;
+        writeln!(ssa, "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding")?;
+        writeln!(
+            ssa,
+            "Style: Default,{},{},&H00FFFFFF,&H00FFFFFF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,0,{},{},{},{},1",
+            settings.font,
+            settings.font_size,
+            settings.alignment,
+            settings.margin_x,
+            settings.margin_x,
+            settings.margin_y
+        )?;
+        writeln!(ssa)?;
+        writeln!(ssa, "[Events]")?;
+        writeln!(
+            ssa,
+            "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text"
+        )?;
+        for line in &self.lines {
+            writeln!(
+                ssa,
+                "Dialogue: 0,{},{},Default,,0,0,0,,{{\\c{}}}{}{{\\c&HFFFFFF&}}: {}",
+                timestamp(line.offset, ".", 2, false),
+                timestamp(line.offset + settings.duration, ".", 2, false),
+                ssa_color(line.color.as_deref()),
+                ssa_escape(&line.name),
+                ssa_escape(&line.text)
+            )?;
+        }
+        Ok(ssa)
+    }
+
+    fn srt(&self, settings: &Settings) -> Result<String, Error> {
+        let mut srt = String::new();
+        for (number, line) in self.lines.iter().enumerate() {
+            writeln!(srt, "{}", number + 1)?;
+            writeln!(
+                srt,
+                "{} --> {}",
+                timestamp(line.offset, ",", 3, true),
+                timestamp(line.offset + settings.duration, ",", 3, true)
+            )?;
+            writeln!(srt, "{}: {}", one_line(&line.name), one_line(&line.text))?;
+            writeln!(srt)?;
+        }
+        Ok(srt)
+    }
+
+    fn vtt(&self, settings: &Settings) -> Result<String, Error> {
+        let mut vtt = String::from("WEBVTT\n\n");
+        for line in &self.lines {
+            writeln!(
+                vtt,
+                "{} --> {}",
+                timestamp(line.offset, ".", 3, true),
+                timestamp(line.offset + settings.duration, ".", 3, true)
+            )?;
+            writeln!(
+                vtt,
+                "<v {name}>{name}: {}",
+                vtt_escape(&line.text),
+                name = vtt_escape(&line.name)
+            )?;
+            writeln!(vtt)?;
+        }
+        Ok(vtt)
+    }
+
+    fn txt(&self) -> Result<String, Error> {
+        let mut txt = String::new();
+        for line in &self.lines {
+            writeln!(
+                txt,
+                "[{}] {}: {}",
+                timestamp(line.offset, ".", 0, false),
+                one_line(&line.name),
+                one_line(&line.text)
+            )?;
+        }
+        Ok(txt)
+    }
+}
+
+impl From<ChatRoot> for Chat {
+    fn from(root: ChatRoot) -> Self {
+        let lines = root
+            .comments
+            .into_iter()
+            .map(|comment| Line {
+                offset: comment.content_offset_seconds,
+                name: comment.commenter.display_name,
+                color: comment.message.user_color,
+                emotes: comment
+                    .message
+                    .fragments
+                    .into_iter()
+                    .filter(|fragment| fragment.emoticon.is_some())
+                    .map(|fragment| fragment.text)
+                    .collect(),
+                text: comment.message.body,
+            })
+            .collect();
+        Self {
+            title: root.video.title,
+            lines,
+        }
+    }
+}
+
+/// Renders the configured formats from `{id}.chat.json`, then compresses the
+/// JSON and every rendered file, keeping the ones that already exist.
+pub(super) fn process(id: &str, dir: &Path, context: &Context) -> Result<(), Error> {
+    let (settings, compression) = (&context.chat, &context.compression);
+    if settings.formats.is_empty() && compression.codec == Codec::None {
+        return Err(Error::Expected);
+    }
+    let json = dir.join(format!("{id}.chat.json"));
+    if !compression.exists(&json) {
+        return Err(Error::NoChatFound);
+    }
+    let pending: Vec<Format> = settings
+        .formats
+        .iter()
+        .copied()
+        .filter(|format| !compression.exists(&dir.join(format.filename(id))))
+        .collect();
+    if !pending.is_empty() {
+        let chat = Chat::read(compression.existing(&json))?;
+        for format in &pending {
+            let rendered = chat.render(*format, settings)?;
+            write_file(dir.join(format.filename(id)), rendered.as_bytes())?;
+        }
+    }
+    let mut compressed = false;
+    let files = settings.formats.iter().map(|format| format.filename(id));
+    for path in std::iter::once(json).chain(files.map(|name| dir.join(name))) {
+        match compression.compress(&path) {
+            Ok(()) => compressed = true,
+            Err(Error::ProcessedChatAlreadyExists | Error::Expected) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    match pending.is_empty() && !compressed {
+        true => Err(Error::ProcessedChatAlreadyExists),
+        false => Ok(()),
+    }
+}
+
+impl ChatTask {
+    pub(crate) fn run(&self) -> u8 {
+        let (files, failed) = match &self.command {
+            ChatCommand::Render {
+                files,
+                formats,
+                output_dir,
+            } => (
+                files,
+                each(files, |file| {
+                    render_file(file, formats, output_dir.as_deref(), self)
+                }),
+            ),
+            ChatCommand::Html { files, output_dir } => (
+                files,
+                each(files, |file| {
+                    let dir = output_dir_of(file, output_dir.as_deref())?;
+                    let path = html::render_file(file, &name(file), &dir)?;
+                    rendered(&path);
+                    Ok(())
+                }),
+            ),
+            ChatCommand::Stats {
+                files,
+                top,
+                threshold,
+                chapters,
+                output_dir,
+            } => (
+                files,
+                each(files, |file| {
+                    let dir = output_dir_of(file, output_dir.as_deref())?;
+                    let (stats, path) =
+                        stats::stats_file(file, &name(file), &dir, *top, *threshold, *chapters)?;
+                    println!("{}\n", stats.table());
+                    rendered(&path);
+                    Ok(())
+                }),
+            ),
+        };
+        match failed {
+            0 => report::SUCCESS,
+            failed if failed == files.len() => report::TOTAL_FAILURE,
+            _ => report::PARTIAL_FAILURE,
+        }
+    }
+}
+
+/// Runs `render` on every file, returning how many failed.
+fn each(files: &[PathBuf], render: impl Fn(&Path) -> Result<(), Error>) -> usize {
+    let mut failed = 0;
+    for file in files {
+        if let Err(err) = render(file) {
+            error(
+                &format!("Could not render {}: {}", file.display(), err.describe()),
+                None,
+            );
+            failed += 1;
+        }
+    }
+    failed
+}
+
+fn render_file(
+    file: &Path,
+    formats: &[Format],
+    output_dir: Option<&Path>,
+    task: &ChatTask,
+) -> Result<(), Error> {
+    let chat = Chat::read(file)?;
+    let name = name(file);
+    let dir = output_dir_of(file, output_dir)?;
+    for format in formats {
+        let path = dir.join(format.filename(&name));
+        write_file(&path, chat.render(*format, &task.settings)?.as_bytes())?;
+        rendered(&path);
+    }
+    Ok(())
+}
+
+/// Creates and returns `output_dir`, defaulting to the directory of `file`.
+fn output_dir_of(file: &Path, output_dir: Option<&Path>) -> Result<PathBuf, Error> {
+    let dir = match output_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    fs::create_dir_all(&dir).map_err(|err| Error::io(err, &dir))?;
+    Ok(dir)
+}
+
+fn rendered(path: &Path) {
+    println!(
+        "{}",
+        colorize(
+            Some("chat"),
+            &format!("Rendered {}", path.display()),
+            Color::BrightGreen
+        )
+    );
+}
+
+/// Name of a chat file without its compression and `.chat.json` extensions.
+pub(super) fn name(path: &Path) -> String {
+    let name = compress::strip(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    name.strip_suffix(".chat.json")
+        .or_else(|| name.strip_suffix(".json"))
+        .or_else(|| name.strip_suffix(".ssa"))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+/// Formats seconds as `h:mm:ss` followed by `digits` fractional digits.
+pub(super) fn timestamp(seconds: f64, separator: &str, digits: u32, pad_hours: bool) -> String {
+    let scale = 10_u64.pow(digits);
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let units = (seconds.max(0.0) * scale as f64).round() as u64;
+    let whole = units / scale;
+    let hours = match pad_hours {
+        true => format!("{:02}", whole / 3600),
+        false => (whole / 3600).to_string(),
+    };
+    let time = format!("{hours}:{:02}:{:02}", whole / 60 % 60, whole % 60);
+    match digits {
+        0 => time,
+        _ => format!(
+            "{time}{separator}{:0width$}",
+            units % scale,
+            width = digits as usize
+        ),
+    }
+}
+
+/// Parses an SSA `h:mm:ss.cc` timestamp into seconds.
+fn seconds(timestamp: &str) -> Option<f64> {
+    let mut parts = timestamp.splitn(3, ':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Converts a `#RRGGBB` color into SSA's `&HBBGGRR&`.
+fn ssa_color(color: Option<&str>) -> String {
+    match color.and_then(|color| color.strip_prefix('#')) {
+        Some(hex) if hex.len() == 6 && hex.is_ascii() => {
+            format!("&H{}{}{}&", &hex[4..6], &hex[2..4], &hex[0..2])
+        }
+        _ => "&HFFFFFF&".to_string(),
+    }
+}
+
+fn ssa_escape(text: &str) -> String {
+    one_line(text)
+        .replace('\\', "\\\\")
+        .replace('{', "\\{")
+        .replace('}', "\\}")
+}
+
+/// Removes override blocks and escapes from SSA text.
+fn ssa_unescape(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => match chars.next() {
+                Some('N' | 'n' | 'h') => plain.push(' '),
+                Some(char) => plain.push(char),
+                None => plain.push('\\'),
+            },
+            '{' => {
+                for char in chars.by_ref() {
+                    if char == '}' {
+                        break;
+                    }
+                }
+            }
+            char => plain.push(char),
+        }
+    }
+    plain
+}
+
+pub(super) fn vtt_escape(text: &str) -> String {
+    one_line(text)
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn one_line(text: &str) -> String {
+    text.replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::temp_dir;
+    use super::*;
+
+    fn chat() -> Chat {
+        let line = |offset, name: &str, color: Option<&str>, text: &str| Line {
+            offset,
+            name: name.to_string(),
+            color: color.map(str::to_string),
+            text: text.to_string(),
+            emotes: Vec::new(),
+        };
+        Chat {
+            title: "Title\nsecond line".to_string(),
+            lines: vec![
+                line(3.456, "Ann", Some("#FF8000"), "hi {there} \\o/"),
+                line(3725.5, "B<b>", None, "a & b\nline"),
+            ],
+        }
+    }
+
+    fn lines(chat: &Chat) -> Vec<(f64, &str, &str, Option<&str>)> {
+        chat.lines
+            .iter()
+            .map(|line| {
+                let (name, text) = (line.name.as_str(), line.text.as_str());
+                (line.offset, name, text, line.color.as_deref())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn timestamps() {
+        let cases = [
+            ((0.0, ".", 2, false), "0:00:00.00"),
+            ((-1.0, ".", 2, false), "0:00:00.00"),
+            ((59.999, ".", 2, false), "0:01:00.00"),
+            ((3.456, ",", 3, true), "00:00:03,456"),
+            ((36000.0, ".", 3, true), "10:00:00.000"),
+            ((3725.5, ".", 0, false), "1:02:06"),
+        ];
+        for ((seconds, separator, digits, pad_hours), expected) in cases {
+            assert_eq!(timestamp(seconds, separator, digits, pad_hours), expected);
+        }
+    }
+
+    #[test]
+    fn renders_every_format() {
+        let chat = chat();
+        let settings = Settings::default();
+        let ssa = chat.render(Format::Ssa, &settings).unwrap();
+        assert!(ssa.contains("\nTitle: Title second line\n"));
+        let events: Vec<&str> = ssa
+            .lines()
+            .filter(|line| line.starts_with("Dialogue:"))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "Dialogue: 0,0:00:03.46,0:00:08.46,Default,,0,0,0,,{\\c&H0080FF&}Ann{\\c&HFFFFFF&}: hi \\{there\\} \\\\o/",
+                "Dialogue: 0,1:02:05.50,1:02:10.50,Default,,0,0,0,,{\\c&HFFFFFF&}B<b>{\\c&HFFFFFF&}: a & b line",
+            ]
+        );
+        let cases = [
+            (
+                Format::Srt,
+                "1\n00:00:03,456 --> 00:00:08,456\nAnn: hi {there} \\o/\n\n\
+                 2\n01:02:05,500 --> 01:02:10,500\nB<b>: a & b line\n\n",
+            ),
+            (
+                Format::Vtt,
+                "WEBVTT\n\n\
+                 00:00:03.456 --> 00:00:08.456\n<v Ann>Ann: hi {there} \\o/\n\n\
+                 01:02:05.500 --> 01:02:10.500\n<v B&lt;b&gt;>B&lt;b&gt;: a &amp; b line\n\n",
+            ),
+            (
+                Format::Txt,
+                "[0:00:03] Ann: hi {there} \\o/\n[1:02:06] B<b>: a & b line\n",
+            ),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(
+                chat.render(format, &settings).unwrap(),
+                expected,
+                "{format}"
+            );
+        }
+    }
+
+    #[test]
+    fn reads_rendered_ssa() {
+        let dir = temp_dir("chat-ssa");
+        let path = dir.join("1.ssa");
+        let ssa = chat().render(Format::Ssa, &Settings::default()).unwrap();
+        let extra = "Comment: 0,0:00:01.00,0:00:06.00,Default,,0,0,0,,skipped: line\n\
+                     Dialogue: 0,0:00:04.00,0:00:09.00,Default,,0,0,0,,no name\n\
+                     Dialogue: 0,0:00:05.00,0:00:10.00,Default,,0,0,0,,{\\b1}C{\\b0}: a, b\\Nc\n";
+        fs::write(&path, ssa + extra).unwrap();
+        let chat = Chat::read(&path).unwrap();
+        assert_eq!(chat.title, "Title second line");
+        assert_eq!(
+            lines(&chat),
+            [
+                (3.46, "Ann", "hi {there} \\o/", None),
+                (3725.5, "B<b>", "a & b line", None),
+                (5.0, "C", "a, b c", None),
+            ]
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reads_chat_downloader_messages() {
+        let dir = temp_dir("chat-cd");
+        let path = dir.join("stream.json");
+        let messages = serde_json::json!([
+            {
+                "message": "first",
+                "timestamp": 1_000_000_000,
+                "author": { "name": "ann", "display_name": "Ann", "colour": "#FF0000" },
+                "emotes": [{ "name": "Kappa" }]
+            },
+            { "message": "", "timestamp": 1_500_000_000, "author": { "name": "empty" } },
+            { "message": "later", "timestamp": 1_002_500_000, "author": { "name": "bob" } },
+            { "message": "replay", "time_in_seconds": 12.0, "author": { "name": "cat" } }
+        ]);
+        fs::write(&path, messages.to_string()).unwrap();
+        let chat = Chat::read(&path).unwrap();
+        assert_eq!(chat.title, "stream");
+        assert_eq!(
+            lines(&chat),
+            [
+                (0.0, "Ann", "first", Some("#FF0000")),
+                (2.5, "bob", "later", None),
+                (12.0, "cat", "replay", None),
+            ]
+        );
+        assert_eq!(chat.lines[0].emotes, ["Kappa"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
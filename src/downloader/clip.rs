@@ -1,15 +1,19 @@
+use super::metadata::{VideoMetadata, SCHEMA_VERSION};
 use super::twitch::{get, ChannelData, PagedTwitchResponse};
-use super::utils::{colorize, loggers, message, VideoInfo};
-use super::{common, twitch, Context};
+use super::utils::{
+    colorize, command, finish_part, from_raw, loggers, message, part_path, remove_part, verified,
+    VideoInfo,
+};
+use super::{chat, common, retry, twitch, Context};
 use crate::init::external::External;
 use crate::Error;
-use chrono::{SecondsFormat, Utc};
+use chrono::{SecondsFormat, TimeZone, Utc};
 use colored::Color;
 use fancy_regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::fmt::{self, Display, Formatter};
 use std::path::Path;
-use std::process::Command;
 use std::sync::LazyLock;
 
 static ID_REGEX: LazyLock<Regex> =
@@ -23,19 +27,19 @@ static CHANNEL_REGEX: LazyLock<Regex> = LazyLock::new(|| unsafe {
         .unwrap_unchecked()
 });
 
-pub(super) fn download<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
+pub(super) fn download<T: VideoInfo>(info: &T, context: &Context) -> Result<(), Error> {
     common::download(
         info,
         context,
         common::save_json,
         common::get_thumbnail,
         get_chat,
-        process_chat,
+        chat::process,
         get_video,
     )
 }
 
-pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &mut Context) -> Result<Vec<T>, Error> {
+pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &Context) -> Result<Vec<T>, Error> {
     common::get_ids(
         data,
         "clip",
@@ -47,7 +51,7 @@ pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &mut Context) -> Result
 
 pub(super) fn get_channel_ids<T: VideoInfo>(
     channel: &str,
-    context: &mut Context,
+    context: &Context,
 ) -> Result<Vec<T>, Error> {
     let ChannelData { username, id } = twitch::get_channel(channel, context)?;
     message(
@@ -63,16 +67,32 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
     let mut after = String::new();
     let interval = context.interval;
     let range = context.range;
-    let now = Utc::now();
+    let mut now = Utc::now();
     let mut start = now - range;
+    // Only search the part of the range between --after and --before
+    if let Some(after) = context
+        .filter
+        .after
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+    {
+        start = start.max(Utc.from_utc_datetime(&after));
+    }
+    if let Some(before) = context
+        .filter
+        .before
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+    {
+        now = now.min(Utc.from_utc_datetime(&before));
+    }
     'date: loop {
         let end = start + interval;
         let start_string = start.to_rfc3339_opts(SecondsFormat::Secs, true);
         let end_string = end.to_rfc3339_opts(SecondsFormat::Secs, true);
         'page: loop {
             let url = format!(
-        "https://api.twitch.tv/helix/clips?first=100&broadcaster_id={id}&after={after}&started_at={start_string}&ended_at={end_string}",
-      );
+                "{}/clips?first=100&broadcaster_id={id}&after={after}&started_at={start_string}&ended_at={end_string}",
+                context.endpoints.twitch_helix
+            );
             message(&format!("[get_channel_ids] URL: {url}"), context, 3);
             let response = get(&url, context)?;
             message(
@@ -80,7 +100,7 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
                 context,
                 3,
             );
-            let data: PagedTwitchResponse<T> = match serde_json::from_str(&response) {
+            let data: PagedTwitchResponse<Value> = match serde_json::from_str(&response) {
                 Ok(data) => data,
                 Err(err) => {
                     message(&format!("[get_channel_ids] JSON Error: {err}"), context, 2);
@@ -88,7 +108,7 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
                 }
             };
             for video in data.data {
-                videos.push(video);
+                videos.push(from_raw(video)?);
             }
             match data.pagination.cursor {
                 Some(cursor) if cursor != after || after.is_empty() => after = cursor,
@@ -100,60 +120,57 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
             break 'date;
         }
     }
-    Ok(videos)
+    Ok(context.filter.apply(videos, context))
 }
 
-fn get_chat(id: &str, context: &mut Context) -> Result<(), Error> {
+fn get_chat(id: &str, dir: &Path, context: &Context) -> Result<(), Error> {
     let chat_string = format!("{id}.chat.json");
-    let chat = Path::new(&chat_string);
-    if chat.exists() {
+    let chat = &dir.join(&chat_string);
+    if context.compression.exists(chat) {
         return Err(Error::AlreadyExists);
     }
     if context.missing.contains(&External::TdCli) {
         return Err(Error::MissingProgram(External::TdCli));
     }
-    let (log, err_log) = loggers(&format!("{id}.chat"), context.logging);
+    remove_part(chat)?;
+    let (log, err_log) = loggers(dir.join(format!("{id}.chat")), context.logging);
 
-    let status = Command::new(External::TdCli.command())
-        .args(["chatdownload", "-u", id, "-o", &format!("{id}.chat.json")])
-        .stdout(log)
-        .stderr(err_log)
-        .status()?;
-    if !status.success() {
-        return Err(Error::CommandFailed(External::TdCli));
-    }
-    Ok(())
-}
-
-fn process_chat(_id: &str, _context: &mut Context) -> Result<(), Error> {
-    Err(Error::Expected)
+    retry::status(
+        External::TdCli,
+        command(&External::TdCli, dir)
+            .args(["chatdownload", "-u", id, "-o"])
+            .arg(part_path(&chat_string))
+            .stdout(log)
+            .stderr(err_log),
+        context,
+    )?;
+    finish_part(chat)
 }
 
-fn get_video<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
-    let video_filename = format!("{}.mp4", info.id());
-    let video = Path::new(&video_filename);
+fn get_video<T: VideoInfo>(info: &T, dir: &Path, context: &Context) -> Result<(), Error> {
+    let video = &dir.join(format!("{}.mp4", info.id()));
     let url = format!("https://clips.twitch.tv/{}", info.id());
-    if video.exists() {
+    if verified(video) {
         return Err(Error::AlreadyExists);
     }
-    let (log, err_log) = loggers(&format!("{}.video", info.id()), context.logging);
+    let (log, err_log) = loggers(dir.join(format!("{}.video", info.id())), context.logging);
     if context.missing.contains(&External::YtDlp) {
         return Err(Error::MissingProgram(External::YtDlp));
     }
-    let status = Command::new(External::YtDlp.command())
-        .args([
-            "-N",
-            &context.threads.to_string(),
-            "-o",
-            format!("{}.%(ext)s", info.id()).as_str(),
-            &url,
-        ])
-        .stdout(log)
-        .stderr(err_log)
-        .status()?;
-    if !status.success() {
-        return Err(Error::CommandFailed(External::YtDlp));
-    }
+    retry::status(
+        External::YtDlp,
+        command(&External::YtDlp, dir)
+            .args([
+                "-N",
+                &context.threads.to_string(),
+                "-o",
+                format!("{}.%(ext)s", info.id()).as_str(),
+                &url,
+            ])
+            .stdout(log)
+            .stderr(err_log),
+        context,
+    )?;
     Ok(())
 }
 
@@ -176,6 +193,8 @@ pub(crate) struct Clip {
     duration: f64,
     #[serde(default)]
     vod_offset: Option<u64>,
+    #[serde(skip)]
+    raw: Value,
 }
 
 impl VideoInfo for Clip {
@@ -207,8 +226,35 @@ impl VideoInfo for Clip {
             r#type: "clip".to_string(),
             duration: self.duration.to_string(),
             muted_segments: None,
+            raw: self.raw.clone(),
         }
     }
+    fn metadata(&self) -> VideoMetadata {
+        VideoMetadata {
+            schema_version: SCHEMA_VERSION,
+            platform: "twitch",
+            kind: "clip".to_string(),
+            id: self.id.clone(),
+            title: self.title.clone(),
+            description: None,
+            channel_id: self.broadcaster_id.clone(),
+            channel_login: None,
+            channel_name: self.broadcaster_name.clone(),
+            created_at: self.created_at.clone(),
+            published_at: None,
+            url: self.url.clone(),
+            thumbnail_url: self.thumbnail_url.clone(),
+            duration: Some(self.duration),
+            view_count: Some(self.view_count),
+            language: Some(self.language.clone()),
+            chapters: Vec::new(),
+            moments: None,
+            extra: self.raw.clone(),
+        }
+    }
+    fn set_raw(&mut self, raw: Value) {
+        self.raw = raw;
+    }
 }
 
 impl Display for Clip {
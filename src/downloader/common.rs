@@ -1,142 +1,174 @@
+use super::index::{Entry, Stage};
+use super::report::Reason;
 use super::utils::{colorize, run_template};
+use super::{emotes, html, remux, youtube};
 use crate::init::{Context, VideoType};
 use crate::utils::{
-    download_file, error_msg, good_msg, message, sanitize, split_videos, warn_msg, write_file,
+    download_file, error_msg, good_msg, message, sanitize, split_videos, verified, warn_msg,
     VideoInfo,
 };
 use crate::Error;
+use chrono::DateTime;
 use colored::Color;
 use fancy_regex::Regex;
 use shell_escape::escape;
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::thread;
+
+const CHAT_EXT: &str = ".chat.json";
+
+pub(crate) static OUTPUT_VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| unsafe { Regex::new(r"\{([a-z_]+)(?::([^}]+))?\}").unwrap_unchecked() });
 
 pub(super) fn download<T: VideoInfo>(
     info: &T,
-    context: &mut Context,
-    save_json: fn(&T, &mut Context) -> Result<(), Error>,
-    get_thumbnail: fn(&T, &mut Context) -> Result<(), Error>,
-    get_chat: fn(&str, &mut Context) -> Result<(), Error>,
-    process_chat: fn(&str, &mut Context) -> Result<(), Error>,
-    get_video: fn(&T, &mut Context) -> Result<(), Error>,
+    context: &Context,
+    save_json: fn(&T, &Path, &Context) -> Result<(), Error>,
+    get_thumbnail: fn(&T, &Path, &Context) -> Result<(), Error>,
+    get_chat: fn(&str, &Path, &Context) -> Result<(), Error>,
+    process_chat: fn(&str, &Path, &Context) -> Result<(), Error>,
+    get_video: fn(&T, &Path, &Context) -> Result<(), Error>,
 ) -> Result<(), Error> {
     let id = info.id();
-    let chat_ext = match context.downloader {
-        VideoType::Vod | VideoType::Highlight => ".ssa",
-        VideoType::Clip | VideoType::YouTube => ".chat.json",
-    };
-    let video_title = match context.downloader {
-        VideoType::Vod | VideoType::Highlight | VideoType::YouTube => {
-            format!("{}-v{id}.mp4", sanitize(info.title().to_string(), false))
-        }
-        VideoType::Clip => format!("{id}.mp4"),
-    };
+    let dir = directory(info, context)?;
+    fs::create_dir_all(&dir)?;
+    let chat_ext = CHAT_EXT;
+    let video_title = video_filename(info, &context.downloader);
     let mut vars = HashMap::new();
     vars.insert("id".into(), id.into());
+    vars.insert(
+        "dir".into(),
+        escape(dir.to_string_lossy().into_owned().into()).into(),
+    );
     vars.insert("chat_ext".into(), chat_ext.into());
     vars.insert(
         "video_title".into(),
         escape(video_title.clone().into()).into(),
     );
 
-    let spinner_text = format!(" Saving JSON {id}.json");
-    context.spinner.create(&spinner_text);
-    let result = save_json(info, context);
-    context.spinner.end();
-    parse_result(&result, context, "json", "Download", &format!("{id}.json"));
-    if let Err(error) = result {
-        if error != Error::AlreadyExists {
-            return Err(error);
-        }
-    }
-    for template in context.post_json.clone() {
-        context.spinner.create(" Running post_json");
-        let result = run_template(&template, &vars);
+    // The json, thumbnail, and chat only depend on the info, so they are fetched at once
+    let json = thread::scope(|scope| {
+        scope.spawn(|| {
+            let spinner_text = format!(" Downloading {id}.jpg");
+            context.spinner.create(&spinner_text);
+            let path = dir.join(format!("{id}.jpg"));
+            let result = indexed(id, Stage::Thumbnail, &path, context, |context| {
+                get_thumbnail(info, &dir, context)
+            });
+            context.spinner.end();
+            parse_result(&result, context, "thumbnail", "Download", &path);
+            run_templates("post_thumbnail", &context.post_thumbnail, &vars, context);
+        });
+        scope.spawn(|| {
+            let spinner_text = format!(" Downloading {id}{chat_ext}");
+            context.spinner.create(&spinner_text);
+            let path = dir.join(format!("{id}{chat_ext}"));
+            let result = indexed(id, Stage::Chat, &path, context, |context| {
+                get_chat(id, &dir, context)
+            });
+            context.spinner.end();
+            parse_result(&result, context, "chat", "Download", &path);
+            run_templates("post_chat", &context.post_chat, &vars, context);
+        });
+
+        let spinner_text = format!(" Saving JSON {id}.json");
+        context.spinner.create(&spinner_text);
+        let path = dir.join(format!("{id}.json"));
+        let result = indexed(id, Stage::Json, &path, context, |context| {
+            save_json(info, &dir, context)
+        });
         context.spinner.end();
-        match result.is_ok() {
-            true => good_msg(Some("post_json"), "Success", context),
-            false => error_msg(Some("post_json"), "Failed", context),
+        parse_result(&result, context, "json", "Download", &path);
+        if matches!(
+            result,
+            Ok(()) | Err(Error::AlreadyExists | Error::AlreadyArchived)
+        ) {
+            run_templates("post_json", &context.post_json, &vars, context);
+        }
+        result
+    });
+    if let Err(error) = json {
+        if !matches!(error, Error::AlreadyExists | Error::AlreadyArchived) {
+            return Err(error);
         }
     }
 
-    let spinner_text = format!(" Downloading {id}.jpg");
-    context.spinner.create(&spinner_text);
-    let result = get_thumbnail(info, context);
-    context.spinner.end();
-    parse_result(
-        &result,
-        context,
-        "thumbnail",
-        "Download",
-        &format!("{id}.jpg"),
-    );
-    for template in context.post_thumbnail.clone() {
-        context.spinner.create(" Running post_thumbnail");
-        let result = run_template(&template, &vars);
+    if context.emotes.enabled {
+        let spinner_text = format!(" Archiving emotes for {id}");
+        context.spinner.create(&spinner_text);
+        let path = dir.join(format!("{id}.emotes.json"));
+        let result = indexed(id, Stage::Emotes, &path, context, |context| {
+            emotes::archive(id, &dir, context)
+        });
         context.spinner.end();
-        match result.is_ok() {
-            true => good_msg(Some("post_thumbnail"), "Success", context),
-            false => error_msg(Some("post_thumbnail"), "Failed", context),
-        }
+        parse_result(&result, context, "emotes", "Download", &path);
     }
 
-    let spinner_text = format!(" Downloading {id}{chat_ext}");
-    context.spinner.create(&spinner_text);
-    let result = get_chat(id, context);
-    context.spinner.end();
-    parse_result(
-        &result,
-        context,
-        "chat",
-        "Download",
-        &format!("{id}{chat_ext}"),
-    );
-    for template in context.post_chat.clone() {
-        context.spinner.create(" Running post_chat");
-        let result = run_template(&template, &vars);
+    if context.chat.html {
+        let spinner_text = format!(" Rendering {id}.chat.html");
+        context.spinner.create(&spinner_text);
+        let path = dir.join(format!("{id}.chat.html"));
+        let result = indexed(id, Stage::Html, &path, context, |context| {
+            html::write(id, &dir, context)
+        });
         context.spinner.end();
-        match result.is_ok() {
-            true => good_msg(Some("post_chat"), "Success", context),
-            false => error_msg(Some("post_chat"), "Failed", context),
-        }
+        parse_result(&result, context, "html", "Process", &path);
     }
 
-    let spinner_text = format!(" Processing {id}{chat_ext}");
-    context.spinner.create(&spinner_text);
-    let result = process_chat(id, context);
-    context.spinner.end();
-    parse_result(
-        &result,
-        context,
-        "chat",
-        "Process",
-        &format!("{id}{chat_ext}.br"),
-    );
-    for template in context.post_chat_process.clone() {
-        context.spinner.create(" Running post_chat_process");
-        let result = run_template(&template, &vars);
+    if let Some(path) = processed_chat(id, &dir, context) {
+        let spinner_text = format!(" Processing {id}{chat_ext}");
+        context.spinner.create(&spinner_text);
+        let result = indexed(id, Stage::ChatProcess, &path, context, |context| {
+            process_chat(id, &dir, context)
+        });
         context.spinner.end();
-        match result.is_ok() {
-            true => good_msg(Some("post_chat_process"), "Success", context),
-            false => error_msg(Some("post_chat_process"), "Failed", context),
-        }
+        parse_result(&result, context, "chat", "Process", &path);
+        run_templates(
+            "post_chat_process",
+            &context.post_chat_process,
+            &vars,
+            context,
+        );
     }
 
     if !context.skip_video {
+        let spinner_text = format!(" Waiting to download {video_title}");
+        context.spinner.create(&spinner_text);
+        let permit = context.video_jobs.acquire();
         let spinner_text = format!(" Downloading {video_title}");
         context.spinner.create(&spinner_text);
-        let result = get_video(info, context);
+        let path = dir.join(&video_title);
+        let result = indexed(id, Stage::Video, &path, context, |context| {
+            get_video(info, &dir, context)
+        });
         context.spinner.end();
-        parse_result(&result, context, "video", "Download", &video_title);
-        for template in context.post_video.clone() {
-            context.spinner.create(" Running post_video");
-            let result = run_template(&template, &vars);
+        drop(permit);
+        parse_result(&result, context, "video", "Download", &path);
+        // There is nothing to remux when the video could not be downloaded
+        if context.remux.enabled
+            && matches!(
+                result,
+                Ok(()) | Err(Error::AlreadyExists | Error::AlreadyArchived)
+            )
+        {
+            let spinner_text = format!(" Remuxing {video_title}");
+            context.spinner.create(&spinner_text);
+            let video = path;
+            let path = dir.join(format!("{id}.ffmetadata"));
+            let result = indexed(id, Stage::Remux, &path, context, |context| {
+                remux::remux(info, &dir, &video, context)?;
+                // The remuxed video replaces the one the index has the checksum of
+                context
+                    .index()
+                    .insert(Entry::complete(id, Stage::Video, &video)?)
+            });
             context.spinner.end();
-            match result.is_ok() {
-                true => good_msg(Some("post_video"), "Success", context),
-                false => error_msg(Some("post_video"), "Failed", context),
-            }
+            parse_result(&result, context, "remux", "Process", &path);
         }
+        run_templates("post_video", &context.post_video, &vars, context);
 
         message(
             &colorize(
@@ -151,13 +183,32 @@ pub(super) fn download<T: VideoInfo>(
     Ok(())
 }
 
-fn parse_result(
+/// Runs the `--post-*` templates of a stage, reporting whether each succeeded.
+fn run_templates(
+    name: &str,
+    templates: &[String],
+    vars: &HashMap<String, String>,
+    context: &Context,
+) {
+    for template in templates {
+        context.spinner.create(&format!(" Running {name}"));
+        let result = run_template(template, vars);
+        context.spinner.end();
+        match result.is_ok() {
+            true => good_msg(Some(name), "Success", context),
+            false => error_msg(Some(name), "Failed", context),
+        }
+    }
+}
+
+pub(super) fn parse_result(
     result: &Result<(), Error>,
-    context: &mut Context,
+    context: &Context,
     r#type: &str,
     verb: &str,
-    filename: &str,
+    path: &Path,
 ) {
+    let filename = path.display();
     match result {
         Ok(()) => {
             good_msg(Some(r#type), format!("{verb}ed {filename}"), context);
@@ -165,6 +216,13 @@ fn parse_result(
         Err(Error::AlreadyExists) => {
             warn_msg(Some(r#type), format!("Already exists: {filename}"), context);
         }
+        Err(Error::AlreadyArchived) => {
+            warn_msg(
+                Some(r#type),
+                format!("Already archived: {filename}"),
+                context,
+            );
+        }
         Err(Error::ProcessedChatAlreadyExists) => {
             warn_msg(
                 Some(r#type),
@@ -173,33 +231,237 @@ fn parse_result(
             );
         }
         Err(Error::Expected) => {}
-        Err(_) => {
+        Err(Error::Stage { source, .. }) => {
+            error_msg(
+                Some(r#type),
+                format!(
+                    "Failed to {} {filename}: {}",
+                    verb.to_lowercase(),
+                    source.describe()
+                ),
+                context,
+            );
+        }
+        Err(error) => {
             error_msg(
                 Some(r#type),
-                format!("Failed to {} {filename}", verb.to_lowercase()),
+                format!(
+                    "Failed to {} {filename}: {}",
+                    verb.to_lowercase(),
+                    error.describe()
+                ),
                 context,
             );
         }
     }
 }
 
-type Id2InfoHelper<T> = fn(&[String], &str, &mut Context) -> Result<Vec<T>, Error>;
+pub(super) fn indexed(
+    id: &str,
+    stage: Stage,
+    path: &Path,
+    context: &Context,
+    run: impl FnOnce(&Context) -> Result<(), Error>,
+) -> Result<(), Error> {
+    if context.index().is_complete(id, stage) {
+        let result = Err(Error::AlreadyArchived);
+        context.report().record(id, stage, path, &result);
+        return result;
+    }
+    let result = run(context);
+    context.report().record(id, stage, path, &result);
+    let recorded = match &result {
+        Ok(()) | Err(Error::AlreadyExists | Error::ProcessedChatAlreadyExists) => {
+            if verified(path) {
+                Entry::complete(id, stage, path).and_then(|entry| context.index().insert(entry))
+            } else {
+                Ok(())
+            }
+        }
+        Err(Error::Expected) => Ok(()),
+        Err(_) => context.index().fail(id, stage, path),
+    };
+    if let Err(error) = recorded {
+        error_msg(
+            Some("index"),
+            format!(
+                "Could not update index for {}: {}",
+                path.display(),
+                error.describe()
+            ),
+            context,
+        );
+    }
+    result.map_err(|error| match error {
+        Error::AlreadyExists
+        | Error::ProcessedChatAlreadyExists
+        | Error::Expected
+        | Error::AlreadyArchived => error,
+        error => error.stage(id, stage, path),
+    })
+}
 
-pub(super) fn save_json<T: VideoInfo>(info: &T, _context: &mut Context) -> Result<(), Error> {
-    let path_string = format!("{}.json", &info.id());
-    let path = Path::new(&path_string);
-    if path.exists() {
+pub(super) fn reindex<T: VideoInfo>(info: &T, context: &Context) -> Result<(), Error> {
+    let id = info.id();
+    context.index().remove(id);
+    for (stage, path) in artifacts(info, context)? {
+        let path_string = path.display();
+        if !verified(&path) {
+            warn_msg(
+                Some("index"),
+                format!("Missing {stage}: {path_string}"),
+                context,
+            );
+            continue;
+        }
+        context.spinner.create(&format!(" Indexing {path_string}"));
+        let result =
+            Entry::complete(id, stage, &path).and_then(|entry| context.index().insert(entry));
+        context.spinner.end();
+        match result {
+            Ok(()) => good_msg(
+                Some("index"),
+                format!("Indexed {stage}: {path_string}"),
+                context,
+            ),
+            Err(error) => error_msg(
+                Some("index"),
+                format!("Could not index {path_string}: {}", error.describe()),
+                context,
+            ),
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn artifacts<T: VideoInfo>(
+    info: &T,
+    context: &Context,
+) -> Result<Vec<(Stage, PathBuf)>, Error> {
+    let id = info.id();
+    let dir = directory(info, context)?;
+    let mut artifacts = vec![
+        (Stage::Json, dir.join(format!("{id}.json"))),
+        (Stage::Thumbnail, dir.join(format!("{id}.jpg"))),
+        (
+            Stage::Chat,
+            context
+                .compression
+                .existing(&dir.join(format!("{id}{CHAT_EXT}"))),
+        ),
+    ];
+    if context.emotes.enabled {
+        artifacts.push((Stage::Emotes, dir.join(format!("{id}.emotes.json"))));
+    }
+    if context.chat.html {
+        artifacts.push((Stage::Html, dir.join(format!("{id}.chat.html"))));
+    }
+    if let Some(path) = processed_chat(id, &dir, context) {
+        artifacts.push((Stage::ChatProcess, path));
+    }
+    artifacts.push((
+        Stage::Video,
+        dir.join(video_filename(info, &context.downloader)),
+    ));
+    if context.remux.enabled {
+        artifacts.push((Stage::Remux, dir.join(format!("{id}.ffmetadata"))));
+    }
+    Ok(artifacts)
+}
+
+pub(super) fn directory<T: VideoInfo>(info: &T, context: &Context) -> Result<PathBuf, Error> {
+    let video = info.to_video();
+    let platform = match context.downloader {
+        VideoType::Vod | VideoType::Highlight | VideoType::Clip | VideoType::TwitchLive => "twitch",
+        VideoType::YouTube | VideoType::YouTubeLive => "youtube",
+    };
+    let mut vars = HashMap::new();
+    vars.insert("platform", platform.to_string());
+    vars.insert("type", video.r#type);
+    vars.insert("id", video.id);
+    vars.insert("title", video.title);
+    vars.insert("user_id", video.user_id);
+    vars.insert("user_login", video.user_login);
+    vars.insert("user_name", video.user_name);
+    vars.insert("language", video.language);
+    vars.insert("created_at", video.created_at);
+    vars.insert("published_at", video.published_at);
+    let mut dir = String::new();
+    let mut last = 0;
+    let template = &context.output_template;
+    for captures in OUTPUT_VARIABLE.captures_iter(template) {
+        let captures = captures.map_err(|err| Error::Template(err.to_string()))?;
+        let (Some(whole), Some(name)) = (captures.get(0), captures.get(1)) else {
+            continue;
+        };
+        dir.push_str(&template[last..whole.start()]);
+        last = whole.end();
+        let Some(value) = vars.get(name.as_str()) else {
+            return Err(Error::Template(format!(
+                "Unknown variable {{{}}}",
+                name.as_str()
+            )));
+        };
+        match captures.get(2) {
+            Some(format) => match DateTime::parse_from_rfc3339(value) {
+                Ok(date) => dir.push_str(&date.format(format.as_str()).to_string()),
+                Err(_) => dir.push_str(&sanitize(value.clone(), false)),
+            },
+            None => dir.push_str(&sanitize(value.clone(), false)),
+        }
+    }
+    dir.push_str(&template[last..]);
+    Ok(PathBuf::from(dir))
+}
+
+/// Path the chat processing stage is indexed under, `None` when chat is not processed.
+fn processed_chat(id: &str, dir: &Path, context: &Context) -> Option<PathBuf> {
+    let json = dir.join(format!("{id}{CHAT_EXT}"));
+    context.compression.path(&json).or_else(|| {
+        context
+            .chat
+            .formats
+            .first()
+            .map(|format| dir.join(format.filename(id)))
+    })
+}
+
+pub(super) fn video_filename<T: VideoInfo>(info: &T, downloader: &VideoType) -> String {
+    match downloader {
+        VideoType::Vod | VideoType::Highlight | VideoType::TwitchLive => {
+            filename(info.title().to_string(), info.id())
+        }
+        VideoType::YouTube | VideoType::YouTubeLive => {
+            youtube::filename(info.title().to_string(), info.id())
+        }
+        VideoType::Clip => format!("{}.mp4", info.id()),
+    }
+}
+
+/// Looks up the info of ids, adding the ones it could not find to the list
+/// with the reason when it is known.
+type Id2InfoHelper<T> =
+    fn(&[String], &str, &Context, &mut Vec<(String, Reason)>) -> Result<Vec<T>, Error>;
+
+pub(super) fn save_json<T: VideoInfo>(
+    info: &T,
+    dir: &Path,
+    _context: &Context,
+) -> Result<(), Error> {
+    let path = dir.join(format!("{}.json", &info.id()));
+    if verified(&path) {
         return Err(Error::AlreadyExists);
     }
-    let mut json = serde_json::to_string_pretty(&info)?;
-    json.push('\n');
-    write_file(path, json.as_bytes())
+    info.metadata().write(&path)
 }
 
-pub(super) fn get_thumbnail<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
-    let path_string = format!("{}.jpg", &info.id());
-    let path = Path::new(&path_string);
-    if path.exists() {
+pub(super) fn get_thumbnail<T: VideoInfo>(
+    info: &T,
+    dir: &Path,
+    context: &Context,
+) -> Result<(), Error> {
+    let path = dir.join(format!("{}.jpg", &info.id()));
+    if verified(&path) {
         return Err(Error::AlreadyExists);
     }
     let url = info.thumbnail_url().to_string();
@@ -211,19 +473,29 @@ pub(super) fn get_thumbnail<T: VideoInfo>(info: &T, context: &mut Context) -> Re
 pub(super) fn get_ids<T: VideoInfo>(
     data: &str,
     r#type: &str,
-    context: &mut Context,
+    context: &Context,
     regexen: &[&'static Regex],
     id2info: Id2InfoHelper<T>,
 ) -> Result<Vec<T>, Error> {
     message(&format!("[get_ids] Getting ids for {data}"), context, 3);
     let mut ids = Vec::new();
+    let mut missing = Vec::new();
     for id in split_videos(data) {
-        if let Ok(id) = regex_helper(&id, context, regexen) {
-            ids.push(id);
+        match regex_helper(&id, context, regexen) {
+            Ok(id) => ids.push(id),
+            Err(_) => missing.push((id, Reason::Invalid)),
         }
     }
     message(&format!("[get_ids] ids found: {ids:?}"), context, 3);
-    let info = id2info(&ids, r#type, context)?;
+    let info = id2info(&ids, r#type, context, &mut missing)?;
+    for id in &ids {
+        if !info.iter().any(|info| info.id() == id) && !missing.iter().any(|(m, _)| m == id) {
+            missing.push((id.clone(), Reason::Unavailable));
+        }
+    }
+    for (id, reason) in &missing {
+        record_missing(id, *reason, context);
+    }
     if info.is_empty() {
         Err(Error::NoMatches)
     } else {
@@ -231,9 +503,30 @@ pub(super) fn get_ids<T: VideoInfo>(
     }
 }
 
+/// Reports a requested video that will not be archived, and indexes it unless
+/// the input was not an id at all.
+fn record_missing(id: &str, reason: Reason, context: &Context) {
+    error_msg(
+        Some("input"),
+        format!("Skipping {id}, it is {reason}"),
+        context,
+    );
+    context.report().missing(id, reason);
+    if reason == Reason::Invalid {
+        return;
+    }
+    if let Err(err) = context.index().unavailable(id, reason) {
+        message(
+            &format!("Could not index {id}: {}", err.describe()),
+            context,
+            -1,
+        );
+    }
+}
+
 pub(super) fn regex_helper(
     text: &str,
-    context: &mut Context,
+    context: &Context,
     regexen: &[&Regex],
 ) -> Result<String, Error> {
     for regex in regexen {
@@ -265,3 +558,62 @@ pub(super) fn filename(title: String, id: &str) -> String {
     let filename = format!("{title}-v{id}.mp4");
     filename
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::twitch::Video;
+    use super::super::utils::temp_dir;
+    use super::*;
+    use crate::init::endpoints::Endpoints;
+
+    fn directory_of(template: &str, title: &str) -> Result<PathBuf, Error> {
+        let dir = temp_dir("directory");
+        let mut context = Context::test(Endpoints::default(), &dir);
+        context.output_template = template.to_string();
+        let mut video = Video::test("1", &[]);
+        video.title = title.to_string();
+        let directory = directory(&video, &context);
+        let _ = fs::remove_dir_all(dir);
+        directory
+    }
+
+    #[test]
+    fn directory_sanitizes_variables() {
+        let cases = [
+            ("..", "/archive/_/1"),
+            (".", "/archive/_/1"),
+            ("a/b", "/archive/a⧸b/1"),
+            ("../../etc/passwd", "/archive/⧸..⧸etc⧸passwd/1"),
+            ("a\\..\\b", "/archive/a⧹..⧹b/1"),
+        ];
+        for (title, expected) in cases {
+            let directory = directory_of("/archive/{title}/{id}", title).unwrap();
+            assert_eq!(directory, PathBuf::from(expected), "{title}");
+            assert_eq!(directory.components().count(), 4, "{title}");
+        }
+    }
+
+    #[test]
+    fn directory_formats_dates() {
+        let cases = [
+            ("{created_at:%Y/%m-%d}", "2024/01-01"),
+            ("{created_at:%Y}/{user_login}", "2024/channel"),
+            ("{platform}/{type}/{user_id}", "twitch/archive/42"),
+            ("{created_at}", "2024-01-01T00_00_00Z"),
+            // Not a date, so the format is ignored
+            ("{title:%Y}", "Video 1"),
+        ];
+        for (template, expected) in cases {
+            let directory = directory_of(template, "Video 1").unwrap();
+            assert_eq!(directory, PathBuf::from(expected), "{template}");
+        }
+    }
+
+    #[test]
+    fn directory_rejects_unknown_variables() {
+        assert!(matches!(
+            directory_of("/archive/{channel}", "Video 1"),
+            Err(Error::Template(message)) if message == "Unknown variable {channel}"
+        ));
+    }
+}
@@ -0,0 +1,341 @@
+use super::utils::{finish_part, part_path, remove_part, verified};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Compression {
+    /// Codec used to compress chat files
+    pub(crate) codec: Codec,
+    /// Codec specific level, the highest one is used when missing
+    pub(crate) level: Option<u32>,
+    /// Delete uncompressed files once the compressed ones are verified
+    pub(crate) delete_original: bool,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            codec: Codec::Brotli,
+            level: None,
+            delete_original: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Codec {
+    Brotli,
+    Zstd,
+    Xz,
+    Gzip,
+    None,
+}
+
+impl Codec {
+    const ALL: [Codec; 4] = [Codec::Brotli, Codec::Zstd, Codec::Xz, Codec::Gzip];
+
+    pub(crate) fn extension(self) -> Option<&'static str> {
+        match self {
+            Codec::Brotli => Some("br"),
+            Codec::Zstd => Some("zst"),
+            Codec::Xz => Some("xz"),
+            Codec::Gzip => Some("gz"),
+            Codec::None => None,
+        }
+    }
+
+    fn from_path(path: &Path) -> Codec {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        Codec::ALL
+            .into_iter()
+            .find(|codec| codec.extension() == extension)
+            .unwrap_or(Codec::None)
+    }
+
+    fn max_level(self) -> u32 {
+        match self {
+            Codec::Brotli => 11,
+            Codec::Zstd => 19,
+            Codec::Xz | Codec::Gzip => 9,
+            Codec::None => 0,
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = Error;
+
+    fn from_str(codec: &str) -> Result<Self, Self::Err> {
+        match codec {
+            "brotli" => Ok(Codec::Brotli),
+            "zstd" => Ok(Codec::Zstd),
+            "xz" => Ok(Codec::Xz),
+            "gzip" => Ok(Codec::Gzip),
+            "none" => Ok(Codec::None),
+            _ => Err(Error::Config(format!("Unknown compression codec: {codec}"))),
+        }
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Codec::Brotli => write!(f, "brotli"),
+            Codec::Zstd => write!(f, "zstd"),
+            Codec::Xz => write!(f, "xz"),
+            Codec::Gzip => write!(f, "gzip"),
+            Codec::None => write!(f, "none"),
+        }
+    }
+}
+
+impl Compression {
+    /// Path of the compressed copy of `path`, `None` when compression is disabled.
+    pub(crate) fn path(&self, path: &Path) -> Option<PathBuf> {
+        let extension = self.codec.extension()?;
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{extension}"));
+        Some(path.with_file_name(name))
+    }
+
+    /// Whether `path` or its compressed copy exists.
+    pub(crate) fn exists(&self, path: &Path) -> bool {
+        verified(path) || self.path(path).is_some_and(verified)
+    }
+
+    /// The existing one of `path` and its compressed copy, preferring `path`.
+    pub(crate) fn existing(&self, path: &Path) -> PathBuf {
+        match self.path(path) {
+            Some(compressed) if !verified(path) && verified(&compressed) => compressed,
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// Compresses `path`, checks that the result decompresses back into it,
+    /// and deletes `path` if configured to.
+    pub(crate) fn compress(&self, path: &Path) -> Result<(), Error> {
+        let Some(compressed) = self.path(path) else {
+            return Err(Error::Expected);
+        };
+        if verified(&compressed) {
+            return Err(Error::ProcessedChatAlreadyExists);
+        }
+        if !verified(path) {
+            return Err(Error::NoChatFound);
+        }
+        remove_part(&compressed)?;
+        let part = part_path(&compressed);
+        let level = self
+            .level
+            .unwrap_or(self.codec.max_level())
+            .min(self.codec.max_level());
+        let mut input = BufReader::new(File::open(path).map_err(|err| Error::io(err, path))?);
+        let output = BufWriter::new(File::create(&part).map_err(|err| Error::io(err, &part))?);
+        encode(self.codec, level, &mut input, output).map_err(|err| Error::io(err, &part))?;
+        if !round_trips(path, &part)? {
+            remove_part(&compressed)?;
+            return Err(Error::RoundTrip(compressed));
+        }
+        finish_part(&compressed)?;
+        if self.delete_original {
+            fs::remove_file(path).map_err(|err| Error::io(err, path))?;
+        }
+        Ok(())
+    }
+}
+
+/// `path` without the extension of the codec it is compressed with.
+pub(crate) fn strip(path: &Path) -> PathBuf {
+    match Codec::from_path(path) {
+        Codec::None => path.to_path_buf(),
+        _ => path.with_extension(""),
+    }
+}
+
+/// Opens `path`, decompressing it according to its extension.
+pub(crate) fn open(path: &Path) -> Result<Box<dyn Read>, Error> {
+    let file = BufReader::new(File::open(path).map_err(|err| Error::io(err, path))?);
+    decoder(Codec::from_path(path), file).map_err(|err| Error::io(err, path))
+}
+
+fn encode(
+    codec: Codec,
+    level: u32,
+    input: &mut impl Read,
+    output: BufWriter<File>,
+) -> io::Result<()> {
+    match codec {
+        Codec::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(output, 4096, level, 22);
+            io::copy(input, &mut encoder)?;
+            encoder.flush()?;
+            encoder
+                .into_inner()
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()
+        }
+        Codec::Zstd => {
+            let level = i32::try_from(level).unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+            let mut encoder = zstd::Encoder::new(output, level)?;
+            io::copy(input, &mut encoder)?;
+            encoder
+                .finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()
+        }
+        Codec::Xz => {
+            let mut encoder = xz2::write::XzEncoder::new(output, level);
+            io::copy(input, &mut encoder)?;
+            encoder
+                .finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()
+        }
+        Codec::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(output, flate2::Compression::new(level));
+            io::copy(input, &mut encoder)?;
+            encoder
+                .finish()?
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()
+        }
+        Codec::None => {
+            let mut output = output;
+            io::copy(input, &mut output)?;
+            output
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()
+        }
+    }
+}
+
+fn decoder(codec: Codec, input: impl Read + 'static) -> io::Result<Box<dyn Read>> {
+    Ok(match codec {
+        Codec::Brotli => Box::new(brotli::Decompressor::new(input, 4096)),
+        Codec::Zstd => Box::new(zstd::Decoder::new(input)?),
+        Codec::Xz => Box::new(xz2::read::XzDecoder::new(input)),
+        Codec::Gzip => Box::new(flate2::read::GzDecoder::new(input)),
+        Codec::None => Box::new(input),
+    })
+}
+
+/// Whether the compressed `part` decompresses into the contents of `original`.
+fn round_trips(original: &Path, part: &Path) -> Result<bool, Error> {
+    let compressed = BufReader::new(File::open(part).map_err(|err| Error::io(err, part))?);
+    let codec = Codec::from_path(part);
+    let mut decoded = decoder(codec, compressed).map_err(|err| Error::io(err, part))?;
+    let mut original =
+        BufReader::new(File::open(original).map_err(|err| Error::io(err, original))?);
+    let mut left = [0; 8192];
+    let mut right = [0; 8192];
+    loop {
+        let read = original.read(&mut left).map_err(Error::from)?;
+        if read == 0 {
+            return Ok(decoded
+                .read(&mut right[..1])
+                .map_err(|err| Error::io(err, part))?
+                == 0);
+        }
+        if decoded
+            .read_exact(&mut right[..read])
+            .map_err(|err| Error::io(err, part))
+            .is_err()
+            || left[..read] != right[..read]
+        {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::temp_dir;
+    use super::*;
+
+    fn chat() -> String {
+        let comments: Vec<String> = (0..2000)
+            .map(|n| format!(r#"{{"offset":{n},"body":"message {n}"}}"#))
+            .collect();
+        format!("[{}]", comments.join(","))
+    }
+
+    fn read(path: &Path) -> String {
+        let mut text = String::new();
+        open(path).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn every_codec_round_trips() {
+        let dir = temp_dir("compress");
+        for codec in Codec::ALL {
+            let path = dir.join(format!("{codec}.chat.json"));
+            fs::write(&path, chat()).unwrap();
+            let compression = Compression {
+                codec,
+                level: None,
+                delete_original: true,
+            };
+            compression.compress(&path).unwrap();
+            let compressed = compression.path(&path).unwrap();
+            assert!(!path.exists(), "{codec}");
+            assert!(!part_path(&compressed).exists(), "{codec}");
+            assert!(fs::metadata(&compressed).unwrap().len() < chat().len() as u64);
+            assert_eq!(Codec::from_path(&compressed), codec);
+            assert_eq!(strip(&compressed), path);
+            assert_eq!(read(&compressed), chat(), "{codec}");
+            assert_eq!(compression.existing(&path), compressed);
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn original_is_kept_unless_the_round_trip_is_verified() {
+        let dir = temp_dir("compress-verify");
+        let path = dir.join("1.chat.json");
+        let other = dir.join("2.chat.json");
+        fs::write(&path, chat()).unwrap();
+        fs::write(&other, "[]").unwrap();
+        for codec in Codec::ALL {
+            let compression = Compression {
+                codec,
+                level: Some(1),
+                delete_original: false,
+            };
+            compression.compress(&path).unwrap();
+            let compressed = compression.path(&path).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), chat());
+            assert!(round_trips(&path, &compressed).unwrap());
+            assert!(!round_trips(&other, &compressed).unwrap(), "{codec}");
+
+            let bytes = fs::read(&compressed).unwrap();
+            let truncated = dir.join(format!("truncated.{}", codec.extension().unwrap()));
+            fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+            assert!(!round_trips(&path, &truncated).unwrap_or(false), "{codec}");
+
+            let deleting = Compression {
+                delete_original: true,
+                ..compression
+            };
+            assert!(matches!(
+                deleting.compress(&path),
+                Err(Error::ProcessedChatAlreadyExists)
+            ));
+            assert!(path.exists());
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,745 @@
+use super::chat::ChatRoot;
+use super::compress;
+use super::utils::{lock, message, relative, verified, warn_msg, write_file};
+use super::{retry, Context, VideoType};
+use crate::Error;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Version 2 made the stored files relative to the manifest.
+const MANIFEST_VERSION: u32 = 2;
+/// Stored images by the URL they were downloaded from, kept in the store.
+const STORE_INDEX: &str = "index.json";
+
+/// Held while the store index is written, as videos are archived in parallel.
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Emotes {
+    /// Archive the emotes and badges used in every chat
+    pub(crate) enabled: bool,
+    /// Directory shared by every video that images are stored in by checksum
+    pub(crate) store: PathBuf,
+}
+
+impl Default for Emotes {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            store: PathBuf::from("emotes"),
+        }
+    }
+}
+
+/// Emotes and badges used by a chat, written to `{id}.emotes.json`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Manifest {
+    pub(crate) version: u32,
+    /// Emotes by the code typed in chat
+    pub(crate) emotes: BTreeMap<String, Image>,
+    /// Badges by `set/version` for Twitch and by title for YouTube
+    pub(crate) badges: BTreeMap<String, Image>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct Image {
+    pub(crate) provider: String,
+    pub(crate) id: String,
+    pub(crate) url: String,
+    /// Path in the store, relative to the manifest's directory
+    pub(crate) file: String,
+    pub(crate) sha256: String,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, an empty one when it does not exist.
+    pub(crate) fn read(path: &Path) -> Result<Self, Error> {
+        if !verified(path) {
+            return Ok(Self::default());
+        }
+        let bytes = fs::read(path).map_err(|err| Error::io(err, path))?;
+        serde_json::from_slice(&bytes).map_err(|err| Error::json(err, path))
+    }
+
+    /// Path of a stored image, for a manifest read from `dir`.
+    pub(crate) fn file(&self, image: &Image, dir: &Path) -> PathBuf {
+        match self.version {
+            // Older manifests stored paths relative to the working directory
+            0 | 1 => PathBuf::from(&image.file),
+            _ => dir.join(&image.file),
+        }
+    }
+}
+
+/// An image in the store, named by its checksum.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Stored {
+    /// File name in the store
+    file: String,
+    sha256: String,
+}
+
+type StoreIndex = BTreeMap<String, Stored>;
+
+/// An emote or badge found in chat that has not been downloaded yet.
+#[derive(Debug, Clone)]
+struct Source {
+    provider: &'static str,
+    id: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CdMessage {
+    #[serde(default)]
+    emotes: Vec<CdEmote>,
+    #[serde(default)]
+    author: CdAuthor,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CdAuthor {
+    #[serde(default)]
+    badges: Vec<CdBadge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CdEmote {
+    id: String,
+    name: String,
+    #[serde(default)]
+    images: Vec<CdImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CdBadge {
+    title: String,
+    #[serde(default)]
+    icons: Vec<CdImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CdImage {
+    url: String,
+    #[serde(default)]
+    width: u32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BttvChannel {
+    #[serde(default, rename = "channelEmotes")]
+    channel_emotes: Vec<BttvEmote>,
+    #[serde(default, rename = "sharedEmotes")]
+    shared_emotes: Vec<BttvEmote>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BttvEmote {
+    id: String,
+    code: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FfzSets {
+    #[serde(default)]
+    default_sets: Vec<u64>,
+    #[serde(default)]
+    sets: HashMap<String, FfzSet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfzSet {
+    #[serde(default)]
+    emoticons: Vec<FfzEmote>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfzEmote {
+    id: u64,
+    name: String,
+    #[serde(default)]
+    urls: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SevenTvSet {
+    #[serde(default)]
+    emotes: Vec<SevenTvEmote>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SevenTvUser {
+    #[serde(default)]
+    emote_set: Option<SevenTvSet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SevenTvEmote {
+    id: String,
+    name: String,
+    data: SevenTvData,
+}
+
+#[derive(Debug, Deserialize)]
+struct SevenTvData {
+    host: SevenTvHost,
+}
+
+#[derive(Debug, Deserialize)]
+struct SevenTvHost {
+    url: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct HelixBadges {
+    #[serde(default)]
+    data: Vec<HelixBadgeSet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelixBadgeSet {
+    set_id: String,
+    versions: Vec<HelixBadge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelixBadge {
+    id: String,
+    image_url_2x: String,
+}
+
+/// Downloads every emote and badge used in `{id}.chat.json` into the store
+/// and writes `{id}.emotes.json` mapping them to the stored files.
+pub(super) fn archive(id: &str, dir: &Path, context: &Context) -> Result<(), Error> {
+    let manifest_path = dir.join(format!("{id}.emotes.json"));
+    if verified(&manifest_path) {
+        return Err(Error::AlreadyExists);
+    }
+    let json = context
+        .compression
+        .existing(&dir.join(format!("{id}.chat.json")));
+    if !verified(&json) {
+        return Err(Error::NoChatFound);
+    }
+    let chat: Value =
+        serde_json::from_reader(compress::open(&json)?).map_err(|err| Error::json(err, &json))?;
+    let (emotes, badges) = match chat {
+        Value::Array(_) => {
+            let messages: Vec<CdMessage> =
+                serde_json::from_value(chat).map_err(|err| Error::json(err, &json))?;
+            let provider = match context.downloader {
+                VideoType::YouTube | VideoType::YouTubeLive => "youtube",
+                _ => "twitch",
+            };
+            cd_sources(messages, provider)
+        }
+        chat => {
+            let root: ChatRoot =
+                serde_json::from_value(chat).map_err(|err| Error::json(err, &json))?;
+            twitch_sources(&root, context)?
+        }
+    };
+
+    let store = &context.emotes.store;
+    fs::create_dir_all(store).map_err(|err| Error::io(err, store))?;
+    let mut manifest = Manifest {
+        version: MANIFEST_VERSION,
+        ..Manifest::default()
+    };
+    let known = read_index(store);
+    let mut added = StoreIndex::new();
+    let mut failed = 0;
+    let total = emotes.len() + badges.len();
+    for (done, (code, source, badge)) in emotes
+        .into_iter()
+        .map(|(code, source)| (code, source, false))
+        .chain(badges.into_iter().map(|(key, source)| (key, source, true)))
+        .enumerate()
+    {
+        context
+            .spinner
+            .create(&format!(" Archiving emotes for {id} {done}/{total}"));
+        let stored = match known.get(&source.url) {
+            Some(stored) if verified(store.join(&stored.file)) => Ok(stored.clone()),
+            _ => store_image(&source, store, context).inspect(|stored| {
+                added.insert(source.url.clone(), stored.clone());
+            }),
+        };
+        let stored = match stored {
+            Ok(stored) => stored,
+            Err(error) => {
+                message(
+                    &format!("[emotes] Could not download {code}: {}", error.describe()),
+                    context,
+                    1,
+                );
+                failed += 1;
+                continue;
+            }
+        };
+        let path = store.join(&stored.file);
+        let image = Image {
+            provider: source.provider.to_string(),
+            id: source.id,
+            url: source.url,
+            file: relative(dir, &path)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .into_owned(),
+            sha256: stored.sha256,
+        };
+        match badge {
+            true => manifest.badges.insert(code, image),
+            false => manifest.emotes.insert(code, image),
+        };
+    }
+    if !added.is_empty() {
+        if let Err(error) = update_index(store, added) {
+            warn_msg(
+                Some("emotes"),
+                format!("Could not update the store index: {}", error.describe()),
+                context,
+            );
+        }
+    }
+    if failed > 0 {
+        warn_msg(
+            Some("emotes"),
+            format!("Could not download {failed} of {total} emotes and badges for {id}"),
+            context,
+        );
+    }
+    let mut json = serde_json::to_string_pretty(&manifest)?;
+    json.push('\n');
+    write_file(manifest_path, json.as_bytes())
+}
+
+type Sources = BTreeMap<String, Source>;
+
+fn cd_sources(messages: Vec<CdMessage>, provider: &'static str) -> (Sources, Sources) {
+    let largest = |images: Vec<CdImage>| images.into_iter().max_by_key(|image| image.width);
+    let mut emotes = Sources::new();
+    let mut badges = Sources::new();
+    for message in messages {
+        for emote in message.emotes {
+            if let Some(image) = largest(emote.images) {
+                emotes.entry(emote.name).or_insert(Source {
+                    provider,
+                    id: emote.id,
+                    url: image.url,
+                });
+            }
+        }
+        for badge in message.author.badges {
+            if let Some(image) = largest(badge.icons) {
+                badges.entry(badge.title.clone()).or_insert(Source {
+                    provider,
+                    id: badge.title,
+                    url: image.url,
+                });
+            }
+        }
+    }
+    (emotes, badges)
+}
+
+fn twitch_sources(root: &ChatRoot, context: &Context) -> Result<(Sources, Sources), Error> {
+    let endpoints = &context.endpoints;
+    let channel = root.streamer.id.to_string();
+    let mut emotes = Sources::new();
+    let mut used_badges = Vec::new();
+    for comment in &root.comments {
+        for fragment in &comment.message.fragments {
+            if let Some(emoticon) = &fragment.emoticon {
+                emotes.entry(fragment.text.clone()).or_insert(Source {
+                    provider: "twitch",
+                    id: emoticon.emoticon_id.clone(),
+                    url: format!(
+                        "{}/{}/default/dark/2.0",
+                        endpoints.twitch_emotes, emoticon.emoticon_id
+                    ),
+                });
+            }
+        }
+        for badge in &comment.message.user_badges {
+            used_badges.push(format!("{}/{}", badge.id, badge.version));
+        }
+    }
+    if root.comments.is_empty() {
+        return Ok((emotes, Sources::new()));
+    }
+
+    let third_party = third_party(root.streamer.id, context);
+    for comment in &root.comments {
+        for word in comment.message.body.split_whitespace() {
+            if emotes.contains_key(word) {
+                continue;
+            }
+            if let Some(source) = third_party.get(word) {
+                emotes.insert(word.to_string(), source.clone());
+            }
+        }
+    }
+
+    let mut urls = vec![format!("{}/chat/badges/global", endpoints.twitch_helix)];
+    if root.streamer.id != 0 {
+        urls.push(format!(
+            "{}/chat/badges?broadcaster_id={channel}",
+            endpoints.twitch_helix
+        ));
+    }
+    let mut available = Sources::new();
+    for url in urls {
+        let sets: HelixBadges = fetch(&url, true, context)?;
+        for set in sets.data {
+            for version in set.versions {
+                available.insert(
+                    format!("{}/{}", set.set_id, version.id),
+                    Source {
+                        provider: "twitch",
+                        id: format!("{}/{}", set.set_id, version.id),
+                        url: version.image_url_2x,
+                    },
+                );
+            }
+        }
+    }
+    let badges = used_badges
+        .into_iter()
+        .filter_map(|key| Some((key.clone(), available.get(&key)?.clone())))
+        .collect();
+    Ok((emotes, badges))
+}
+
+/// BetterTTV, FrankerFaceZ, and 7TV emotes usable in a channel, channel
+/// emotes taking priority over global ones. Lists that cannot be fetched are
+/// left out, so the manifest only misses their emotes.
+fn third_party(channel: u64, context: &Context) -> HashMap<String, Source> {
+    let endpoints = &context.endpoints;
+    let mut emotes = HashMap::new();
+    let bttv = |list: Vec<BttvEmote>, emotes: &mut HashMap<String, Source>| {
+        for emote in list {
+            let url = format!("{}/emote/{}/2x", endpoints.bttv_cdn, emote.id);
+            emotes.insert(
+                emote.code,
+                Source {
+                    provider: "bttv",
+                    id: emote.id,
+                    url,
+                },
+            );
+        }
+    };
+    let ffz = |sets: Vec<FfzSet>, emotes: &mut HashMap<String, Source>| {
+        for emote in sets.into_iter().flat_map(|set| set.emoticons) {
+            let Some(url) = emote.urls.get("2").or_else(|| emote.urls.get("1")) else {
+                continue;
+            };
+            emotes.insert(
+                emote.name,
+                Source {
+                    provider: "ffz",
+                    id: emote.id.to_string(),
+                    url: absolute(url, &endpoints.ffz),
+                },
+            );
+        }
+    };
+    let seventv = |set: SevenTvSet, emotes: &mut HashMap<String, Source>| {
+        for emote in set.emotes {
+            emotes.insert(
+                emote.name,
+                Source {
+                    provider: "7tv",
+                    id: emote.id,
+                    url: format!(
+                        "{}/2x.webp",
+                        absolute(&emote.data.host.url, &endpoints.seventv)
+                    ),
+                },
+            );
+        }
+    };
+
+    let mut global: FfzSets = provider(&format!("{}/set/global", endpoints.ffz), context);
+    let defaults = global
+        .default_sets
+        .iter()
+        .filter_map(|id| global.sets.remove(&id.to_string()))
+        .collect();
+    ffz(defaults, &mut emotes);
+    bttv(
+        provider(&format!("{}/cached/emotes/global", endpoints.bttv), context),
+        &mut emotes,
+    );
+    seventv(
+        provider(&format!("{}/emote-sets/global", endpoints.seventv), context),
+        &mut emotes,
+    );
+    if channel == 0 {
+        return emotes;
+    }
+
+    let room: FfzSets = provider(&format!("{}/room/id/{channel}", endpoints.ffz), context);
+    ffz(room.sets.into_values().collect(), &mut emotes);
+    let user: BttvChannel = provider(
+        &format!("{}/cached/users/twitch/{channel}", endpoints.bttv),
+        context,
+    );
+    bttv(user.shared_emotes, &mut emotes);
+    bttv(user.channel_emotes, &mut emotes);
+    let user: SevenTvUser = provider(
+        &format!("{}/users/twitch/{channel}", endpoints.seventv),
+        context,
+    );
+    seventv(user.emote_set.unwrap_or_default(), &mut emotes);
+    emotes
+}
+
+/// Gets a third-party emote list, warning and treating it as empty when it fails.
+fn provider<T: DeserializeOwned + Default>(url: &str, context: &Context) -> T {
+    fetch(url, false, context).unwrap_or_else(|error| {
+        warn_msg(
+            Some("emotes"),
+            format!("Could not get emotes from {url}: {}", error.describe()),
+            context,
+        );
+        T::default()
+    })
+}
+
+/// Gets a provider's JSON, treating a missing channel as having no emotes.
+fn fetch<T: DeserializeOwned + Default>(
+    url: &str,
+    twitch: bool,
+    context: &Context,
+) -> Result<T, Error> {
+    message(&format!("[emotes] URL: {url}"), context, 3);
+    let response = retry::send(context, || match twitch {
+        true => context
+            .client
+            .get(url)
+            .header("Client-ID", &context.client_id)
+            .bearer_auth(&context.token),
+        false => context.client.get(url),
+    })?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(T::default());
+    }
+    let response = response.error_for_status()?.text()?;
+    message(&format!("[emotes] Response: {response}"), context, 4);
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// Downloads an image into the store under its checksum, unless it is already there.
+fn store_image(source: &Source, store: &Path, context: &Context) -> Result<Stored, Error> {
+    let response = retry::send(context, || context.client.get(&source.url))?.error_for_status()?;
+    let extension = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("image/"))
+        .map(|value| value.split(';').next().unwrap_or(value).trim().to_string())
+        .filter(|value| !value.is_empty() && value.chars().all(char::is_alphanumeric))
+        .unwrap_or_else(|| "img".to_string());
+    let bytes = response.bytes()?;
+    let sha256 = format!("{:x}", Sha256::digest(&bytes));
+    let file = format!("{sha256}.{extension}");
+    let path = store.join(&file);
+    if !verified(&path) {
+        write_file(&path, &bytes)?;
+    }
+    Ok(Stored { file, sha256 })
+}
+
+/// Reads the store index, an empty one when it is missing or unreadable.
+fn read_index(store: &Path) -> StoreIndex {
+    fs::read(store.join(STORE_INDEX))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Adds newly stored images to the store index.
+fn update_index(store: &Path, added: StoreIndex) -> Result<(), Error> {
+    let _lock = lock(&STORE_LOCK);
+    let mut index = read_index(store);
+    index.extend(added);
+    write_file(store.join(STORE_INDEX), &serde_json::to_vec(&index)?)
+}
+
+/// Resolves a protocol relative URL with the scheme of the provider's endpoint.
+fn absolute(url: &str, endpoint: &str) -> String {
+    let scheme = endpoint
+        .split_once("//")
+        .map_or("https:", |(scheme, _)| scheme);
+    match url.strip_prefix("//") {
+        Some(url) => format!("{scheme}//{url}"),
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::chat::{self, Badge, Comment, Fragment, FragmentEmoticon, Message, Streamer};
+    use super::super::utils::{serve, temp_dir};
+    use super::*;
+    use crate::init::endpoints::Endpoints;
+    use serde_json::json;
+
+    fn chat(dir: &Path, id: &str) {
+        let comment = Comment {
+            message: Message {
+                body: "Kappa catJAM Sevn ZreknarF".to_string(),
+                fragments: vec![
+                    Fragment {
+                        text: "Kappa".to_string(),
+                        emoticon: Some(FragmentEmoticon {
+                            emoticon_id: "25".to_string(),
+                        }),
+                    },
+                    Fragment {
+                        text: " catJAM Sevn ZreknarF".to_string(),
+                        emoticon: None,
+                    },
+                ],
+                user_badges: vec![Badge {
+                    id: "subscriber".to_string(),
+                    version: "12".to_string(),
+                }],
+                ..Message::default()
+            },
+            ..Comment::default()
+        };
+        let root = ChatRoot {
+            streamer: Streamer {
+                name: "Channel".to_string(),
+                id: 42,
+            },
+            comments: vec![comment],
+            ..ChatRoot::default()
+        };
+        chat::write(dir.join(format!("{id}.chat.json")), &root).unwrap();
+    }
+
+    /// Emote and badge lists in the order they are requested, with `url` as every host.
+    fn lists(url: &str) -> Vec<String> {
+        [
+            json!({ "default_sets": [3], "sets": { "3": { "emoticons": [
+                { "id": 1, "name": "ZreknarF", "urls": { "1": format!("{url}/ffz/1") } }
+            ]}}}),
+            json!([{ "id": "b1", "code": "catJAM" }]),
+            json!({ "emotes": [{ "id": "s1", "name": "Sevn", "data": { "host": { "url": format!("{url}/7tv/s1") } } }] }),
+            json!({ "sets": {} }),
+            json!({ "channelEmotes": [], "sharedEmotes": [] }),
+            json!({ "emote_set": null }),
+            json!({ "data": [{ "set_id": "subscriber", "versions": [
+                { "id": "12", "image_url_2x": format!("{url}/badge/12") }
+            ]}]}),
+            json!({ "data": [] }),
+        ]
+        .iter()
+        .map(Value::to_string)
+        .collect()
+    }
+
+    fn context(url: &str, dir: &Path) -> Context {
+        let endpoints = Endpoints {
+            twitch_gql: url.to_string(),
+            twitch_helix: url.to_string(),
+            twitch_emotes: url.to_string(),
+            bttv: url.to_string(),
+            bttv_cdn: url.to_string(),
+            ffz: url.to_string(),
+            seventv: url.to_string(),
+        };
+        let mut context = Context::test(endpoints, dir);
+        context.emotes.store = dir.join("store");
+        context
+    }
+
+    /// Archives the emotes of `id`, with the lists pointing at images served
+    /// from `images_url`, returning the paths of the lists it requested.
+    fn archive_with(id: &str, dir: &Path, images_url: &str) -> Vec<String> {
+        let (url, server) = serve(lists(images_url));
+        let mut context = context(&url, dir);
+        context.endpoints.twitch_emotes = images_url.to_string();
+        context.endpoints.bttv_cdn = images_url.to_string();
+        chat(dir, id);
+        archive(id, dir, &context).unwrap();
+        server
+            .join()
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    #[test]
+    fn emotes_are_stored_once_and_reused() {
+        let dir = temp_dir("emotes-store");
+        // Kappa, Sevn, ZreknarF, catJAM and the badge, Kappa and catJAM being the same image
+        let images = ["same", "seven", "ffz", "same", "badge"];
+        let (images_url, images_server) = serve(images.map(str::to_string).to_vec());
+        assert_eq!(archive_with("1", &dir, &images_url).len(), 8);
+        let paths: Vec<String> = images_server
+            .join()
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "/25/default/dark/2.0",
+                "/7tv/s1/2x.webp",
+                "/ffz/1",
+                "/emote/b1/2x",
+                "/badge/12"
+            ]
+        );
+        let stored = fs::read_dir(dir.join("store"))
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name() != STORE_INDEX)
+            .count();
+        assert_eq!(stored, 4);
+
+        let manifest = Manifest::read(&dir.join("1.emotes.json")).unwrap();
+        assert_eq!(manifest.version, MANIFEST_VERSION);
+        assert_eq!(manifest.emotes.len(), 4);
+        assert_eq!(manifest.emotes["Kappa"].provider, "twitch");
+        assert_eq!(manifest.emotes["catJAM"].provider, "bttv");
+        assert_eq!(manifest.emotes["Sevn"].provider, "7tv");
+        assert_eq!(manifest.emotes["ZreknarF"].provider, "ffz");
+        assert_eq!(
+            manifest.emotes["Kappa"].file,
+            manifest.emotes["catJAM"].file
+        );
+        assert!(manifest.emotes["Kappa"].file.starts_with("store"));
+        let kappa = manifest.file(&manifest.emotes["Kappa"], &dir);
+        assert_eq!(fs::read(kappa).unwrap(), b"same");
+        assert_eq!(
+            fs::read(manifest.file(&manifest.badges["subscriber/12"], &dir)).unwrap(),
+            b"badge"
+        );
+
+        // A second video only fetches the lists, as its images are already stored
+        // and the server that had them is gone
+        assert_eq!(archive_with("2", &dir, &images_url).len(), 8);
+        let second = Manifest::read(&dir.join("2.emotes.json")).unwrap();
+        for (code, image) in &manifest.emotes {
+            assert_eq!(second.emotes[code].file, image.file);
+            assert_eq!(second.emotes[code].sha256, image.sha256);
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+}
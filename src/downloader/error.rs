@@ -1,76 +1,144 @@
+use super::index::Stage;
 use super::External;
 use std::fmt::Display;
+use std::path::{Path, PathBuf};
 use std::{fmt, io, string};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub(crate) enum Error {
-    Io,
-    JsonParseFailed,
+    Io {
+        source: io::Error,
+        path: Option<PathBuf>,
+    },
+    JsonParseFailed {
+        source: serde_json::Error,
+        path: Option<PathBuf>,
+    },
     AlreadyExists,
+    AlreadyArchived,
+    Incomplete(PathBuf),
+    RoundTrip(PathBuf),
     NoChatFound,
     ProcessedChatAlreadyExists,
     NoRegexMatch,
     NoMatches,
     NoType,
     ConfigFileMissing,
-    ConfigParseFailed,
-    ConfigSerializeFailed,
-    Request,
-    Format,
+    ConfigParseFailed(toml::de::Error),
+    ConfigSerializeFailed(toml::ser::Error),
+    Request(reqwest::Error),
+    Format(fmt::Error),
+    Utf8(string::FromUtf8Error),
     MissingProgram(External),
-    CommandFailed(External),
+    CommandFailed(External, Option<i32>),
     Expected,
     Token(String),
     Config(String),
-    Template,
+    Index(String),
+    Template(String),
+    Quota(String),
+    Gql(String),
+    Api(String),
+    Stage {
+        id: String,
+        stage: Stage,
+        path: PathBuf,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    pub(crate) fn io(source: io::Error, path: impl AsRef<Path>) -> Self {
+        Error::Io {
+            source,
+            path: Some(path.as_ref().to_path_buf()),
+        }
+    }
+    pub(crate) fn json(source: serde_json::Error, path: impl AsRef<Path>) -> Self {
+        Error::JsonParseFailed {
+            source,
+            path: Some(path.as_ref().to_path_buf()),
+        }
+    }
+    pub(crate) fn stage(self, id: &str, stage: Stage, path: impl AsRef<Path>) -> Self {
+        Error::Stage {
+            id: id.to_string(),
+            stage,
+            path: path.as_ref().to_path_buf(),
+            source: Box::new(self),
+        }
+    }
+    pub(crate) fn describe(&self) -> String {
+        let mut description = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            description.push_str(&format!(": {error}"));
+            source = error.source();
+        }
+        description
+    }
 }
-// todo!() Make better errors with information as to what went wrong
 
 impl From<io::Error> for Error {
-    fn from(_: io::Error) -> Error {
-        Error::Io
+    fn from(source: io::Error) -> Error {
+        Error::Io { source, path: None }
     }
 }
 
 impl From<serde_json::Error> for Error {
-    fn from(_: serde_json::Error) -> Error {
-        Error::JsonParseFailed
+    fn from(source: serde_json::Error) -> Error {
+        Error::JsonParseFailed { source, path: None }
     }
 }
 
 impl From<toml::de::Error> for Error {
-    fn from(_: toml::de::Error) -> Error {
-        Error::ConfigParseFailed
+    fn from(error: toml::de::Error) -> Error {
+        Error::ConfigParseFailed(error)
     }
 }
 
 impl From<toml::ser::Error> for Error {
-    fn from(_: toml::ser::Error) -> Error {
-        Error::ConfigSerializeFailed
+    fn from(error: toml::ser::Error) -> Error {
+        Error::ConfigSerializeFailed(error)
     }
 }
 
 impl From<reqwest::Error> for Error {
-    fn from(_: reqwest::Error) -> Error {
-        Error::Request
+    fn from(error: reqwest::Error) -> Error {
+        Error::Request(error)
     }
 }
 
 impl From<fmt::Error> for Error {
-    fn from(_: fmt::Error) -> Error {
-        Error::Format
+    fn from(error: fmt::Error) -> Error {
+        Error::Format(error)
     }
 }
 
 impl From<string::FromUtf8Error> for Error {
-    fn from(_: string::FromUtf8Error) -> Error {
-        Error::Io
+    fn from(error: string::FromUtf8Error) -> Error {
+        Error::Utf8(error)
     }
 }
 
 impl From<strfmt::FmtError> for Error {
-    fn from(_: strfmt::FmtError) -> Error {
-        Error::Template
+    fn from(error: strfmt::FmtError) -> Error {
+        Error::Template(error.to_string())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::JsonParseFailed { source, .. } => Some(source),
+            Error::ConfigParseFailed(source) => Some(source),
+            Error::ConfigSerializeFailed(source) => Some(source),
+            Error::Format(source) => Some(source),
+            Error::Utf8(source) => Some(source),
+            Error::Stage { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
     }
 }
 
@@ -78,23 +146,76 @@ impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Error::AlreadyExists => write!(f, "Already exists"),
+            Error::AlreadyArchived => write!(f, "Already archived"),
+            Error::Incomplete(path) => write!(f, "Output is incomplete: {}", path.display()),
+            Error::RoundTrip(path) => write!(
+                f,
+                "Compressed file does not match its original: {}",
+                path.display()
+            ),
             Error::NoChatFound => write!(f, "No chat found"),
             Error::ProcessedChatAlreadyExists => write!(f, "Compressed chat already exists"),
             Error::NoRegexMatch => write!(f, "No regex match"),
             Error::NoMatches => write!(f, "No matches"),
             Error::NoType => write!(f, "No type"),
-            Error::Io => write!(f, "Reading JSON file failed"),
-            Error::JsonParseFailed => write!(f, "Parsing JSON failed"),
+            Error::Io {
+                path: Some(path), ..
+            } => write!(f, "I/O error on {}", path.display()),
+            Error::Io { path: None, .. } => write!(f, "I/O error"),
+            Error::JsonParseFailed {
+                path: Some(path), ..
+            } => write!(f, "Parsing JSON file {} failed", path.display()),
+            Error::JsonParseFailed { path: None, .. } => write!(f, "Parsing JSON failed"),
             Error::ConfigFileMissing => write!(f, "Config file does not exist"),
-            Error::ConfigParseFailed => write!(f, "Parsing config file failed"),
-            Error::ConfigSerializeFailed => write!(f, "Serializing config file failed"),
-            Error::Request => write!(f, "Request failed"),
-            Error::Format => write!(f, "Formatting failed"),
+            Error::ConfigParseFailed(_) => write!(f, "Parsing config file failed"),
+            Error::ConfigSerializeFailed(_) => write!(f, "Serializing config file failed"),
+            Error::Request(error) => match error.url() {
+                Some(url) => write!(
+                    f,
+                    "Request to {}{} failed: {}",
+                    url.host_str().unwrap_or_default(),
+                    url.path(),
+                    describe_request(error)
+                ),
+                None => write!(f, "Request failed: {}", describe_request(error)),
+            },
+            Error::Format(_) => write!(f, "Formatting failed"),
+            Error::Utf8(_) => write!(f, "Decoding UTF-8 failed"),
             Error::MissingProgram(program) => write!(f, "Missing program: {program}"),
-            Error::CommandFailed(program) => write!(f, "Command failed: {program}"),
+            Error::CommandFailed(program, Some(code)) => {
+                write!(f, "Command failed with exit code {code}: {program}")
+            }
+            Error::CommandFailed(program, None) => write!(f, "Command failed: {program}"),
             Error::Expected => write!(f, "This error is expected"),
-            Error::Token(message) | Error::Config(message) => write!(f, "{message}"),
-            Error::Template => write!(f, "Failed to run template"),
+            Error::Token(message)
+            | Error::Config(message)
+            | Error::Index(message)
+            | Error::Quota(message) => {
+                write!(f, "{message}")
+            }
+            Error::Template(message) => write!(f, "Failed to run template: {message}"),
+            Error::Gql(message) => write!(f, "Twitch GQL request failed: {message}"),
+            Error::Api(message) => write!(f, "API request failed: {message}"),
+            Error::Stage {
+                id, stage, path, ..
+            } => write!(f, "[{stage}] {id} ({})", path.display()),
         }
     }
 }
+
+/// Describes a request error without its URL, which may contain API keys.
+fn describe_request(error: &reqwest::Error) -> String {
+    let mut description = match error.status() {
+        Some(status) => format!("HTTP {status}"),
+        None if error.is_timeout() => "timed out".to_string(),
+        None if error.is_connect() => "could not connect".to_string(),
+        None if error.is_decode() => "could not decode response".to_string(),
+        None => "could not send request".to_string(),
+    };
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        description.push_str(&format!(": {error}"));
+        source = error.source();
+    }
+    description
+}
@@ -0,0 +1,201 @@
+use super::metadata::VideoMetadata;
+use super::utils::{message, VideoInfo};
+use super::Context;
+use chrono::{DateTime, NaiveDate};
+use fancy_regex::Regex;
+use std::cmp::Reverse;
+
+/// Conditions the videos listed from a channel have to meet to be archived.
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    /// First day a video may be created on
+    pub(crate) after: Option<NaiveDate>,
+    /// Last day a video may be created on
+    pub(crate) before: Option<NaiveDate>,
+    /// Seconds
+    pub(crate) min_duration: Option<f64>,
+    /// Seconds
+    pub(crate) max_duration: Option<f64>,
+    pub(crate) match_title: Option<Regex>,
+    pub(crate) reject_title: Option<Regex>,
+    pub(crate) min_views: Option<u64>,
+    /// Most videos to keep, the newest ones
+    pub(crate) limit: Option<usize>,
+}
+
+impl Filter {
+    /// Adds the videos of a listing page that pass to `videos`, newest first.
+    /// Returns `false` once the rest of a newest first listing can be skipped,
+    /// because the page went past `after` or the limit was reached.
+    pub(crate) fn extend<T: VideoInfo>(
+        &self,
+        videos: &mut Vec<T>,
+        page: Vec<T>,
+        context: &Context,
+    ) -> bool {
+        let mut page: Vec<(VideoMetadata, T)> = page
+            .into_iter()
+            .map(|video| (video.metadata(), video))
+            .collect();
+        page.sort_by_key(|(metadata, _)| Reverse(date(metadata)));
+        for (metadata, video) in page {
+            if self.is_full(videos.len()) {
+                return false;
+            }
+            if self.is_older(&metadata) {
+                message(
+                    &format!(
+                        "[filter] Stopping at {}, created before --after",
+                        metadata.id
+                    ),
+                    context,
+                    2,
+                );
+                return false;
+            }
+            if self.accepts(&metadata, context) {
+                videos.push(video);
+            }
+        }
+        !self.is_full(videos.len())
+    }
+
+    /// Keeps the newest videos that pass, for listings in no particular order.
+    pub(crate) fn apply<T: VideoInfo>(&self, videos: Vec<T>, context: &Context) -> Vec<T> {
+        let mut kept = Vec::new();
+        self.extend(&mut kept, videos, context);
+        kept
+    }
+
+    fn accepts(&self, metadata: &VideoMetadata, context: &Context) -> bool {
+        let Some(reason) = self.rejection(metadata) else {
+            return true;
+        };
+        message(
+            &format!("[filter] Skipping {}, {reason}", metadata.id),
+            context,
+            2,
+        );
+        false
+    }
+
+    /// Why a video does not pass, none when it does.
+    fn rejection(&self, metadata: &VideoMetadata) -> Option<&'static str> {
+        let date = date(metadata);
+        let title = |regex: &Regex| regex.is_match(&metadata.title).unwrap_or(false);
+        let duration = metadata.duration.unwrap_or_default();
+        if self
+            .before
+            .is_some_and(|before| date.is_none_or(|date| date > before))
+        {
+            Some("created after --before")
+        } else if self.is_older(metadata) {
+            Some("created before --after")
+        } else if self.min_duration.is_some_and(|min| duration < min) {
+            Some("shorter than --min-duration")
+        } else if self.max_duration.is_some_and(|max| duration > max) {
+            Some("longer than --max-duration")
+        } else if self.match_title.as_ref().is_some_and(|regex| !title(regex)) {
+            Some("title does not match --match-title")
+        } else if self.reject_title.as_ref().is_some_and(title) {
+            Some("title matches --reject-title")
+        } else if self
+            .min_views
+            .is_some_and(|min| metadata.view_count.unwrap_or_default() < min)
+        {
+            Some("fewer views than --min-views")
+        } else {
+            None
+        }
+    }
+
+    fn is_older(&self, metadata: &VideoMetadata) -> bool {
+        self.after
+            .is_some_and(|after| date(metadata).is_none_or(|date| date < after))
+    }
+
+    fn is_full(&self, count: usize) -> bool {
+        self.limit.is_some_and(|limit| count >= limit)
+    }
+}
+
+fn date(metadata: &VideoMetadata) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(&metadata.created_at)
+        .ok()
+        .map(|date| date.date_naive())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::twitch::Video;
+    use super::super::utils::temp_dir;
+    use super::*;
+    use crate::init::endpoints::Endpoints;
+
+    fn page(days: &[u32]) -> Vec<Video> {
+        days.iter()
+            .map(|day| {
+                let mut video = Video::test(&day.to_string(), &[]);
+                video.created_at = format!("2024-01-{day:02}T00:00:00Z");
+                video
+            })
+            .collect()
+    }
+
+    fn ids(videos: &[Video]) -> Vec<&str> {
+        videos.iter().map(VideoInfo::id).collect()
+    }
+
+    #[test]
+    fn extend_stops_at_a_video_before_after() {
+        let dir = temp_dir("filter-after");
+        let context = Context::test(Endpoints::default(), &dir);
+        let filter = Filter {
+            after: NaiveDate::from_ymd_opt(2024, 1, 10),
+            ..Filter::default()
+        };
+        let mut videos = Vec::new();
+        assert!(filter.extend(&mut videos, page(&[20, 15]), &context));
+        assert!(!filter.extend(&mut videos, page(&[11, 12, 5, 10]), &context));
+        assert_eq!(ids(&videos), ["20", "15", "12", "11", "10"]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn extend_stops_once_the_limit_is_reached() {
+        let dir = temp_dir("filter-limit");
+        let context = Context::test(Endpoints::default(), &dir);
+        let filter = Filter {
+            limit: Some(3),
+            reject_title: Regex::new("Video 19").ok(),
+            ..Filter::default()
+        };
+        let mut videos = Vec::new();
+        assert!(filter.extend(&mut videos, page(&[19, 20]), &context));
+        assert!(!filter.extend(&mut videos, page(&[17, 18, 16]), &context));
+        assert_eq!(ids(&videos), ["20", "18", "17"]);
+        assert!(!filter.extend(&mut videos, page(&[1]), &context));
+        assert_eq!(videos.len(), 3);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn apply_keeps_the_newest_that_pass() {
+        let dir = temp_dir("filter-apply");
+        let context = Context::test(Endpoints::default(), &dir);
+        let filter = Filter {
+            before: NaiveDate::from_ymd_opt(2024, 1, 20),
+            min_duration: Some(3600.0),
+            limit: Some(2),
+            ..Filter::default()
+        };
+        let kept = filter.apply(page(&[3, 25, 9, 14]), &context);
+        assert_eq!(ids(&kept), ["14", "9"]);
+        let filter = Filter {
+            max_duration: Some(60.0),
+            ..Filter::default()
+        };
+        assert!(filter.apply(page(&[3]), &context).is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
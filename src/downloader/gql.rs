@@ -0,0 +1,576 @@
+use super::chat::{
+    Badge, ChatRoot, ChatVideo, Comment, Commenter, Emoticon, FileInfo, Fragment, FragmentEmoticon,
+    Message, Streamer,
+};
+use super::twitch::Moment;
+use super::utils::message;
+use super::{retry, Context};
+use crate::Error;
+use chrono::{SecondsFormat, Utc};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Client ID accepted by the GQL endpoint without a user token.
+const CLIENT_ID: &str = "kd1unb4b3q4t58fwlpcbzcbnm76a8fp";
+const COMMENTS_HASH: &str = "b70a3591ff0f4e0313d126c6a1502d79a1c02baebb288227c582044aa76adf6a";
+
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GqlError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VideoData {
+    video: Option<Video>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Video {
+    title: String,
+    description: Option<String>,
+    created_at: String,
+    length_seconds: f64,
+    view_count: u64,
+    game: Option<Game>,
+    owner: Option<Owner>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusData {
+    video: Option<VideoStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VideoStatus {
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Game {
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Owner {
+    id: String,
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MomentsData {
+    video: Option<MomentsVideo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MomentsVideo {
+    length_seconds: f64,
+    game: Option<MomentGame>,
+    moments: Option<Moments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Moments {
+    edges: Vec<MomentEdge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MomentEdge {
+    node: MomentNode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MomentNode {
+    position_milliseconds: u64,
+    duration_milliseconds: u64,
+    details: Option<MomentDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MomentDetails {
+    game: Option<MomentGame>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MomentGame {
+    id: String,
+    display_name: String,
+    #[serde(rename = "boxArtURL")]
+    box_art_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentsData {
+    video: Option<CommentsVideo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentsVideo {
+    comments: Option<Comments>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Comments {
+    edges: Vec<Edge>,
+    page_info: PageInfo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Edge {
+    cursor: Option<String>,
+    node: Node,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    id: String,
+    commenter: Option<GqlCommenter>,
+    content_offset_seconds: f64,
+    created_at: String,
+    message: GqlMessage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlCommenter {
+    id: String,
+    login: String,
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlMessage {
+    #[serde(default)]
+    fragments: Vec<GqlFragment>,
+    #[serde(default)]
+    user_badges: Vec<GqlBadge>,
+    user_color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GqlFragment {
+    emote: Option<GqlEmote>,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlEmote {
+    #[serde(rename = "emoteID")]
+    emote_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GqlBadge {
+    #[serde(rename = "setID")]
+    set_id: String,
+    version: String,
+}
+
+/// Downloads the chat of a Twitch VOD through the GQL API.
+pub(super) fn chat(id: &str, context: &Context) -> Result<ChatRoot, Error> {
+    let video = video(id, context)?;
+    let (streamer, channel_id) = match &video.owner {
+        Some(owner) => (
+            Streamer {
+                name: owner.display_name.clone(),
+                id: owner.id.parse().unwrap_or_default(),
+            },
+            owner.id.clone(),
+        ),
+        None => (Streamer::default(), String::new()),
+    };
+    let length = video.length_seconds;
+    let mut comments = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let variables = match &cursor {
+            Some(cursor) => json!({ "videoID": id, "cursor": cursor }),
+            None => json!({ "videoID": id, "contentOffsetSeconds": 0 }),
+        };
+        let body = json!([{
+            "operationName": "VideoCommentsByOffsetOrCursor",
+            "variables": variables,
+            "extensions": {
+                "persistedQuery": { "version": 1, "sha256Hash": COMMENTS_HASH }
+            }
+        }]);
+        let mut pages: Vec<Response<CommentsData>> = request(&body, context)?;
+        let Some(page) = pages.pop() else {
+            return Err(Error::Gql("Empty response".to_string()));
+        };
+        let Some(data) = data(page)?.video.and_then(|video| video.comments) else {
+            break;
+        };
+        let mut last_cursor = None;
+        for edge in data.edges {
+            last_cursor = edge.cursor;
+            comments.push(comment(edge.node, id, &channel_id));
+        }
+        if let Some(comment) = comments.last() {
+            context.spinner.create(&format!(
+                " Downloading {id}.chat.json {:.0}%",
+                (comment.content_offset_seconds / length.max(1.0) * 100.0).min(100.0)
+            ));
+        }
+        match (data.page_info.has_next_page, last_cursor) {
+            (true, Some(next)) if cursor.as_ref() != Some(&next) => cursor = Some(next),
+            _ => break,
+        }
+    }
+    message(
+        &format!("[gql] Downloaded {} comments for {id}", comments.len()),
+        context,
+        2,
+    );
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    Ok(ChatRoot {
+        file_info: FileInfo {
+            created_at: now.clone(),
+            updated_at: now,
+            ..FileInfo::default()
+        },
+        streamer,
+        video: ChatVideo {
+            title: video.title,
+            description: video.description,
+            id: id.to_string(),
+            created_at: video.created_at,
+            start: 0.0,
+            end: length,
+            length,
+            view_count: video.view_count,
+            game: video.game.map(|game| game.display_name),
+            chapters: Vec::new(),
+        },
+        comments,
+        embedded_data: None,
+    })
+}
+
+/// Fetches the game changes of a Twitch VOD, or the game of the whole video
+/// when it never changed.
+pub(super) fn moments(id: &str, context: &Context) -> Result<Vec<Moment>, Error> {
+    let query = format!(
+        "query{{video(id:\"{id}\"){{lengthSeconds,game{{id,displayName,boxArtURL}},moments(momentRequestType:VIDEO_CHAPTER_MARKERS){{edges{{node{{positionMilliseconds,durationMilliseconds,details{{...on GameChangeMomentDetails{{game{{id,displayName,boxArtURL}}}}}}}}}}}}}}}}"
+    );
+    let response: Response<MomentsData> = request(&json!({ "query": query }), context)?;
+    let video = data(response)?
+        .video
+        .ok_or_else(|| Error::Gql(format!("Video {id} not found")))?;
+    #[allow(clippy::cast_precision_loss)]
+    let moments: Vec<Moment> = video
+        .moments
+        .map(|moments| moments.edges)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|edge| {
+            let game = edge.node.details?.game?;
+            Some(moment(
+                game,
+                edge.node.position_milliseconds as f64 / 1000.0,
+                edge.node.duration_milliseconds as f64 / 1000.0,
+            ))
+        })
+        .collect();
+    match (moments.is_empty(), video.game) {
+        (true, Some(game)) => Ok(vec![moment(game, 0.0, video.length_seconds)]),
+        _ => Ok(moments),
+    }
+}
+
+/// Fetches the status of a Twitch video, e.g. `RECORDED`, none when it does not exist.
+pub(super) fn status(id: &str, context: &Context) -> Result<Option<String>, Error> {
+    let query = format!("query{{video(id:\"{id}\"){{status}}}}");
+    let response: Response<StatusData> = request(&json!({ "query": query }), context)?;
+    Ok(data(response)?.video.map(|video| video.status))
+}
+
+fn moment(game: MomentGame, start: f64, duration: f64) -> Moment {
+    Moment {
+        game_id: game.id,
+        game: game.display_name,
+        box_art_url: game.box_art_url.unwrap_or_default(),
+        start,
+        duration,
+    }
+}
+
+fn video(id: &str, context: &Context) -> Result<Video, Error> {
+    let query = format!(
+        "query{{video(id:\"{id}\"){{title,description,createdAt,lengthSeconds,viewCount,game{{displayName}},owner{{id,displayName}}}}}}"
+    );
+    let response: Response<VideoData> = request(&json!({ "query": query }), context)?;
+    data(response)?
+        .video
+        .ok_or_else(|| Error::Gql(format!("Video {id} not found")))
+}
+
+fn request<T: DeserializeOwned>(body: &Value, context: &Context) -> Result<T, Error> {
+    let response = retry::send(context, || {
+        context
+            .client
+            .post(&context.endpoints.twitch_gql)
+            .header("Client-ID", CLIENT_ID)
+            .json(body)
+    })?
+    .error_for_status()?
+    .text()?;
+    message(&format!("[gql] Response: {response}"), context, 4);
+    Ok(serde_json::from_str(&response)?)
+}
+
+fn data<T>(response: Response<T>) -> Result<T, Error> {
+    match (response.data, response.errors.into_iter().next()) {
+        (Some(data), None) => Ok(data),
+        (_, Some(error)) => Err(Error::Gql(error.message)),
+        (None, None) => Err(Error::Gql("No data".to_string())),
+    }
+}
+
+fn comment(node: Node, video_id: &str, channel_id: &str) -> Comment {
+    let mut body = String::new();
+    let mut fragments = Vec::new();
+    let mut emoticons = Vec::new();
+    for fragment in node.message.fragments {
+        let begin = body.chars().count();
+        body.push_str(&fragment.text);
+        let emoticon = fragment.emote.map(|emote| {
+            emoticons.push(Emoticon {
+                id: emote.emote_id.clone(),
+                begin,
+                end: body.chars().count().saturating_sub(1),
+            });
+            FragmentEmoticon {
+                emoticon_id: emote.emote_id,
+            }
+        });
+        fragments.push(Fragment {
+            text: fragment.text,
+            emoticon,
+        });
+    }
+    let commenter = node
+        .commenter
+        .map_or_else(Commenter::default, |commenter| Commenter {
+            display_name: commenter.display_name,
+            id: commenter.id,
+            name: commenter.login,
+            ..Commenter::default()
+        });
+    Comment {
+        id: node.id,
+        created_at: node.created_at,
+        channel_id: channel_id.to_string(),
+        content_type: "video".to_string(),
+        content_id: video_id.to_string(),
+        content_offset_seconds: node.content_offset_seconds,
+        commenter,
+        message: Message {
+            body,
+            bits_spent: None,
+            fragments,
+            user_badges: node
+                .message
+                .user_badges
+                .into_iter()
+                .map(|badge| Badge {
+                    id: badge.set_id,
+                    version: badge.version,
+                })
+                .collect(),
+            user_color: node.message.user_color,
+            emoticons,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::{serve, temp_dir};
+    use super::*;
+    use crate::init::endpoints::Endpoints;
+
+    /// Context of a test against the server at `url`, by its unique port.
+    fn context(url: String) -> (Context, std::path::PathBuf) {
+        let dir = temp_dir(&format!(
+            "gql-{}",
+            url.rsplit(':').next().unwrap_or_default()
+        ));
+        let endpoints = Endpoints {
+            twitch_gql: url,
+            ..Endpoints::default()
+        };
+        (Context::test(endpoints, &dir), dir)
+    }
+
+    fn comments(edges: &Value, next: bool) -> String {
+        json!([{ "data": { "video": { "comments": {
+            "edges": edges,
+            "pageInfo": { "hasNextPage": next }
+        }}}}])
+        .to_string()
+    }
+
+    #[test]
+    fn chat_follows_cursor_and_maps_comments() {
+        let video = json!({ "data": { "video": {
+            "title": "Title",
+            "description": null,
+            "createdAt": "2024-01-01T00:00:00Z",
+            "lengthSeconds": 100.0,
+            "viewCount": 5,
+            "game": { "displayName": "Game" },
+            "owner": { "id": "42", "displayName": "Streamer" }
+        }}});
+        let first = comments(
+            &json!([{
+                "cursor": "page2",
+                "node": {
+                    "id": "c1",
+                    "commenter": { "id": "7", "login": "viewer", "displayName": "Viewer" },
+                    "contentOffsetSeconds": 10.0,
+                    "createdAt": "2024-01-01T00:00:10Z",
+                    "message": {
+                        "fragments": [
+                            { "emote": null, "text": "hi " },
+                            { "emote": { "emoteID": "25" }, "text": "Kappa" }
+                        ],
+                        "userBadges": [{ "setID": "subscriber", "version": "12" }],
+                        "userColor": "#FF0000"
+                    }
+                }
+            }]),
+            true,
+        );
+        let second = comments(
+            &json!([{
+                "cursor": "page3",
+                "node": {
+                    "id": "c2",
+                    "commenter": null,
+                    "contentOffsetSeconds": 20.0,
+                    "createdAt": "2024-01-01T00:00:20Z",
+                    "message": { "fragments": [{ "emote": null, "text": "bye" }] }
+                }
+            }]),
+            false,
+        );
+        let (url, server) = serve(vec![video.to_string(), first, second]);
+        let (context, dir) = context(url);
+        let chat = chat("123", &context).unwrap();
+        let requests = server.join().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].1.contains("\"contentOffsetSeconds\":0"));
+        assert!(requests[2].1.contains("\"cursor\":\"page2\""));
+
+        assert_eq!(chat.streamer.id, 42);
+        assert_eq!(chat.video.game.as_deref(), Some("Game"));
+        assert_eq!(chat.comments.len(), 2);
+        let comment = &chat.comments[0];
+        assert_eq!(comment.channel_id, "42");
+        assert_eq!(comment.content_id, "123");
+        assert_eq!(comment.commenter.name, "viewer");
+        assert_eq!(comment.message.body, "hi Kappa");
+        assert_eq!(comment.message.bits_spent, None);
+        assert_eq!(comment.message.user_badges[0].id, "subscriber");
+        let emoticon = &comment.message.emoticons[0];
+        assert_eq!(
+            (emoticon.id.as_str(), emoticon.begin, emoticon.end),
+            ("25", 3, 7)
+        );
+        assert_eq!(
+            comment.message.fragments[1]
+                .emoticon
+                .as_ref()
+                .map(|emote| emote.emoticon_id.as_str()),
+            Some("25")
+        );
+        assert_eq!(chat.comments[1].commenter.name, "");
+        assert_eq!(chat.comments[1].message.body, "bye");
+    }
+
+    fn moments_of(video: &Value) -> Vec<Moment> {
+        let (url, server) = serve(vec![json!({ "data": { "video": video } }).to_string()]);
+        let (context, dir) = context(url);
+        let moments = moments("123", &context).unwrap();
+        server.join().unwrap();
+        let _ = std::fs::remove_dir_all(dir);
+        moments
+    }
+
+    #[test]
+    fn moments_parse_game_changes() {
+        let game =
+            |id: &str, name: &str| json!({ "id": id, "displayName": name, "boxArtURL": null });
+        let moments = moments_of(&json!({
+            "lengthSeconds": 600.0,
+            "game": game("1", "Just Chatting"),
+            "moments": { "edges": [
+                { "node": {
+                    "positionMilliseconds": 0,
+                    "durationMilliseconds": 120_000,
+                    "details": { "game": game("1", "Just Chatting") }
+                }},
+                { "node": {
+                    "positionMilliseconds": 120_000,
+                    "durationMilliseconds": 480_500,
+                    "details": { "game": game("2", "Chess") }
+                }},
+                { "node": {
+                    "positionMilliseconds": 300_000,
+                    "durationMilliseconds": 1000,
+                    "details": {}
+                }}
+            ]}
+        }));
+        assert_eq!(moments.len(), 2);
+        assert_eq!(moments[1].game_id, "2");
+        assert_eq!(moments[1].game, "Chess");
+        assert_eq!(moments[1].box_art_url, "");
+        assert!((moments[1].start - 120.0).abs() < f64::EPSILON);
+        assert!((moments[1].duration - 480.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn moments_fall_back_to_the_game_of_the_video() {
+        let moments = moments_of(&json!({
+            "lengthSeconds": 600.0,
+            "game": { "id": "2", "displayName": "Chess", "boxArtURL": "https://example.com/{width}.jpg" },
+            "moments": { "edges": [] }
+        }));
+        assert_eq!(moments.len(), 1);
+        assert_eq!(moments[0].game, "Chess");
+        assert_eq!(moments[0].box_art_url, "https://example.com/{width}.jpg");
+        assert!(moments[0].start.abs() < f64::EPSILON);
+        assert!((moments[0].duration - 600.0).abs() < f64::EPSILON);
+
+        let moments = moments_of(&json!({ "lengthSeconds": 600.0, "game": null, "moments": null }));
+        assert!(moments.is_empty());
+    }
+}
@@ -1,5 +1,5 @@
 use super::utils::VideoInfo;
-use super::{common, twitch, Context};
+use super::{chat, common, twitch, Context};
 use crate::Error;
 use fancy_regex::Regex;
 use std::sync::LazyLock;
@@ -10,19 +10,19 @@ static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| unsafe {
     Regex::new(r"^(?:https://)?(?:www\.)?twitch\.tv/videos/([0-9]+)(?:\?.*)?$").unwrap_unchecked()
 });
 
-pub(super) fn download<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
+pub(super) fn download<T: VideoInfo>(info: &T, context: &Context) -> Result<(), Error> {
     common::download(
         info,
         context,
-        common::save_json,
+        twitch::save_json,
         common::get_thumbnail,
         twitch::get_chat,
-        twitch::process_chat,
+        chat::process,
         twitch::get_video,
     )
 }
 
-pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &mut Context) -> Result<Vec<T>, Error> {
+pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &Context) -> Result<Vec<T>, Error> {
     common::get_ids(
         data,
         "highlight",
@@ -34,7 +34,7 @@ pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &mut Context) -> Result
 
 pub(super) fn get_channel_ids<T: VideoInfo>(
     channel: &str,
-    context: &mut Context,
+    context: &Context,
 ) -> Result<Vec<T>, Error> {
     twitch::get_channel_ids::<T>(channel, "highlight", context)
 }
@@ -0,0 +1,210 @@
+use super::chat::{Chat, Line};
+use super::emotes::Manifest;
+use super::metadata::Metadata;
+use super::utils::{relative, verified, write_file};
+use super::Context;
+use crate::Error;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const STYLE: &str = r#"
+body { margin: 0; background: #18181b; color: #efeff1; font: 14px/1.5 sans-serif; }
+header { position: sticky; top: 0; padding: 12px 16px; background: #0e0e10; border-bottom: 1px solid #2f2f35; }
+h1 { margin: 0 0 4px; font-size: 18px; }
+.info { color: #adadb8; }
+input { width: 100%; box-sizing: border-box; margin-top: 8px; padding: 6px 8px; border: 1px solid #2f2f35; border-radius: 4px; background: #18181b; color: inherit; }
+main { padding: 8px 16px; }
+.m { padding: 2px 0; overflow-wrap: anywhere; }
+.m:target { background: #2f2f35; }
+.t { color: #adadb8; font-variant-numeric: tabular-nums; text-decoration: none; margin-right: 6px; }
+.u { font-weight: bold; color: #9147ff; }
+.m img { height: 28px; vertical-align: middle; }
+"#;
+
+const SCRIPT: &str = r#"
+const search = document.getElementById("search");
+const count = document.getElementById("count");
+const messages = Array.from(document.querySelectorAll(".m"));
+search.addEventListener("input", () => {
+  const query = search.value.toLowerCase();
+  let shown = 0;
+  for (const message of messages) {
+    const match = !query || message.dataset.s.includes(query);
+    message.hidden = !match;
+    shown += match;
+  }
+  count.textContent = shown;
+});
+"#;
+
+/// Writes `{id}.chat.html` from the chat, metadata and emotes archived in `dir`.
+pub(super) fn write(id: &str, dir: &Path, context: &Context) -> Result<(), Error> {
+    let path = dir.join(format!("{id}.chat.html"));
+    if verified(&path) {
+        return Err(Error::AlreadyExists);
+    }
+    let json = context
+        .compression
+        .existing(&dir.join(format!("{id}.chat.json")));
+    if !verified(&json) {
+        return Err(Error::NoChatFound);
+    }
+    write_file(&path, page(&json, dir, id, dir)?.as_bytes())
+}
+
+/// Writes `{name}.chat.html` for a chat file, reading `{name}.json` and
+/// `{name}.emotes.json` from the same directory when they exist.
+pub(super) fn render_file(file: &Path, name: &str, output_dir: &Path) -> Result<PathBuf, Error> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let path = output_dir.join(format!("{name}.chat.html"));
+    write_file(&path, page(file, dir, name, output_dir)?.as_bytes())?;
+    Ok(path)
+}
+
+/// Renders the chat at `json` into a page that will be saved in `output_dir`.
+fn page(json: &Path, dir: &Path, name: &str, output_dir: &Path) -> Result<String, Error> {
+    let chat = Chat::read(json)?;
+    let metadata = Metadata::read(&dir.join(format!("{name}.json")));
+    let manifest = Manifest::read(&dir.join(format!("{name}.emotes.json")))?;
+    let emotes = local_emotes(&manifest, dir, output_dir);
+    let title = metadata.title.clone().unwrap_or_else(|| chat.title.clone());
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>{}</title>", escape(&title))?;
+    writeln!(html, "<style>{STYLE}</style>\n</head>\n<body>\n<header>")?;
+    match &metadata.url {
+        Some(url) => writeln!(
+            html,
+            "<h1><a href=\"{}\" style=\"color: inherit\">{}</a></h1>",
+            escape(url),
+            escape(&title)
+        )?,
+        None => writeln!(html, "<h1>{}</h1>", escape(&title))?,
+    }
+    let info: Vec<String> = [metadata.channel, metadata.date]
+        .into_iter()
+        .flatten()
+        .map(|info| escape(&info))
+        .chain([format!(
+            "<span id=\"count\">{}</span> messages",
+            chat.lines.len()
+        )])
+        .collect();
+    writeln!(
+        html,
+        "<div class=\"info\">{}</div>",
+        info.join(" &middot; ")
+    )?;
+    writeln!(
+        html,
+        "<input id=\"search\" type=\"search\" placeholder=\"Search messages and users\">"
+    )?;
+    writeln!(html, "</header>\n<main>")?;
+    let mut anchored = None;
+    for line in &chat.lines {
+        message(&mut html, line, &emotes, &mut anchored)?;
+    }
+    writeln!(html, "</main>\n<script>{SCRIPT}</script>\n</body>\n</html>")?;
+    Ok(html)
+}
+
+fn message(
+    html: &mut String,
+    line: &Line,
+    emotes: &HashMap<&str, String>,
+    anchored: &mut Option<u64>,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let seconds = line.offset.max(0.0) as u64;
+    // Only the first message of each second is linked to
+    let id = match anchored.replace(seconds) == Some(seconds) {
+        true => String::new(),
+        false => format!(" id=\"t{seconds}\""),
+    };
+    let time = format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    let color = match &line.color {
+        Some(color) if is_hex_color(color) => format!(" style=\"color: {color}\""),
+        _ => String::new(),
+    };
+    let text: Vec<String> = line
+        .text
+        .split(' ')
+        .map(|word| match emotes.get(word) {
+            Some(src) => format!(
+                "<img src=\"{}\" alt=\"{word}\" title=\"{word}\">",
+                escape(src),
+                word = escape(word)
+            ),
+            None => escape(word),
+        })
+        .collect();
+    let search = format!("{} {}", line.name, line.text).to_lowercase();
+    writeln!(
+        html,
+        "<div class=\"m\"{id} data-s=\"{}\"><a class=\"t\" href=\"#t{seconds}\">{time}</a><span class=\"u\"{color}>{}</span>: {}</div>",
+        escape(&search),
+        escape(&line.name),
+        text.join(" ")
+    )?;
+    Ok(())
+}
+
+/// Emote codes mapped to their stored images, relative to `output_dir`,
+/// skipping the images that are no longer in the store.
+fn local_emotes<'a>(
+    manifest: &'a Manifest,
+    dir: &Path,
+    output_dir: &Path,
+) -> HashMap<&'a str, String> {
+    manifest
+        .emotes
+        .iter()
+        .map(|(code, image)| (code, manifest.file(image, dir)))
+        .filter(|(_, file)| verified(file))
+        .filter_map(|(code, file)| {
+            let path = relative(output_dir, &file)?;
+            Some((code.as_str(), url_path(&path)))
+        })
+        .collect()
+}
+
+/// Percent-encodes a relative path for use as a URL.
+fn url_path(path: &Path) -> String {
+    let path = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<String>>()
+        .join("/");
+    let mut url = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                url.push(char::from(byte));
+            }
+            byte => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    url
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,312 @@
+use super::report::Reason;
+use crate::Error;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub(crate) const INDEX_FILE: &str = "archiver.index.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Stage {
+    Json,
+    Thumbnail,
+    Chat,
+    Emotes,
+    Html,
+    ChatProcess,
+    Video,
+    Remux,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum State {
+    Complete,
+    Failed,
+    /// Not found upstream when requested, with the reason in the entry
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct Entry {
+    pub(crate) id: String,
+    pub(crate) stage: Stage,
+    pub(crate) state: State,
+    pub(crate) path: String,
+    pub(crate) size: u64,
+    pub(crate) checksum: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<Reason>,
+    pub(crate) updated_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Index {
+    path: PathBuf,
+    entries: HashMap<(String, Stage), Entry>,
+    /// Lines in the file, more than the entries once some were replaced
+    lines: usize,
+}
+
+impl Index {
+    pub(crate) fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut entries = HashMap::new();
+        let mut lines = 0;
+        if path.exists() {
+            let file = File::open(&path).map_err(|err| Error::io(err, &path))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|err| Error::io(err, &path))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let entry: Entry =
+                    serde_json::from_str(&line).map_err(|err| Error::json(err, &path))?;
+                entries.insert((entry.id.clone(), entry.stage), entry);
+                lines += 1;
+            }
+        }
+        let mut index = Self {
+            path,
+            entries,
+            lines,
+        };
+        index.compact()?;
+        Ok(index)
+    }
+
+    /// Rewrites the file without the entries that were replaced by later ones.
+    pub(crate) fn compact(&mut self) -> Result<(), Error> {
+        match self.lines > self.entries.len() {
+            true => self.save(),
+            false => Ok(()),
+        }
+    }
+
+    pub(crate) fn get(&self, id: &str, stage: Stage) -> Option<&Entry> {
+        self.entries.get(&(id.to_string(), stage))
+    }
+
+    pub(crate) fn is_complete(&self, id: &str, stage: Stage) -> bool {
+        self.get(id, stage)
+            .is_some_and(|entry| entry.state == State::Complete)
+    }
+
+    pub(crate) fn is_archived(&self, id: &str, stages: &[Stage]) -> bool {
+        stages.iter().all(|stage| self.is_complete(id, *stage))
+    }
+
+    pub(crate) fn fail(&mut self, id: &str, stage: Stage, path: &Path) -> Result<(), Error> {
+        if self.is_complete(id, stage) {
+            return Ok(());
+        }
+        self.redo(id, stage, path)
+    }
+
+    /// Marks a stage to be run again, even when it was complete.
+    pub(crate) fn redo(&mut self, id: &str, stage: Stage, path: &Path) -> Result<(), Error> {
+        self.insert(Entry {
+            id: id.to_string(),
+            stage,
+            state: State::Failed,
+            path: path.to_string_lossy().into_owned(),
+            size: 0,
+            checksum: String::new(),
+            reason: None,
+            updated_at: now(),
+        })
+    }
+
+    /// Records that `id` was requested but not found upstream, keeping the
+    /// metadata of a video that was archived before it went missing.
+    pub(crate) fn unavailable(&mut self, id: &str, reason: Reason) -> Result<(), Error> {
+        if self.is_complete(id, Stage::Json) {
+            return Ok(());
+        }
+        self.insert(Entry {
+            id: id.to_string(),
+            stage: Stage::Json,
+            state: State::Unavailable,
+            path: String::new(),
+            size: 0,
+            checksum: String::new(),
+            reason: Some(reason),
+            updated_at: now(),
+        })
+    }
+
+    pub(crate) fn remove(&mut self, id: &str) {
+        self.entries.retain(|(entry_id, _), _| entry_id != id);
+    }
+
+    pub(crate) fn save(&mut self) -> Result<(), Error> {
+        let mut entries: Vec<&Entry> = self.entries.values().collect();
+        entries.sort_by(|a, b| (&a.id, a.stage as u8).cmp(&(&b.id, b.stage as u8)));
+        let mut buffer = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut buffer, entry)?;
+            buffer.push(b'\n');
+        }
+        let temp = self.path.with_extension("jsonl.tmp");
+        fs::write(&temp, buffer).map_err(|err| Error::io(err, &temp))?;
+        fs::rename(&temp, &self.path).map_err(|err| Error::io(err, &self.path))?;
+        self.lines = self.entries.len();
+        Ok(())
+    }
+
+    pub(crate) fn insert(&mut self, entry: Entry) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|err| Error::io(err, &self.path))?;
+        self.entries.insert((entry.id.clone(), entry.stage), entry);
+        self.lines += 1;
+        Ok(())
+    }
+}
+
+impl Entry {
+    pub(crate) fn complete(id: &str, stage: Stage, path: &Path) -> Result<Self, Error> {
+        let (size, checksum) = checksum(path)?;
+        Ok(Self {
+            id: id.to_string(),
+            stage,
+            state: State::Complete,
+            path: path.to_string_lossy().into_owned(),
+            size,
+            checksum,
+            reason: None,
+            updated_at: now(),
+        })
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Stage::Json => write!(f, "json"),
+            Stage::Thumbnail => write!(f, "thumbnail"),
+            Stage::Chat => write!(f, "chat"),
+            Stage::Emotes => write!(f, "emotes"),
+            Stage::Html => write!(f, "html"),
+            Stage::ChatProcess => write!(f, "chat_process"),
+            Stage::Video => write!(f, "video"),
+            Stage::Remux => write!(f, "remux"),
+        }
+    }
+}
+
+pub(crate) fn checksum(path: impl AsRef<Path>) -> Result<(u64, String), Error> {
+    let path = path.as_ref();
+    let mut file = File::open(path).map_err(|err| Error::io(err, path))?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher).map_err(|err| Error::io(err, path))?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::temp_dir;
+    use super::*;
+
+    fn lines(path: &Path) -> usize {
+        fs::read_to_string(path).unwrap().lines().count()
+    }
+
+    #[test]
+    fn checksum_is_kept_per_stage() {
+        let dir = temp_dir("index-checksum");
+        let (json, video) = (dir.join("1.json"), dir.join("1.mp4"));
+        fs::write(&json, "{}").unwrap();
+        fs::write(&video, "video").unwrap();
+        let mut index = Index::load(dir.join(INDEX_FILE)).unwrap();
+        index
+            .insert(Entry::complete("1", Stage::Json, &json).unwrap())
+            .unwrap();
+        index
+            .insert(Entry::complete("1", Stage::Video, &video).unwrap())
+            .unwrap();
+
+        let index = Index::load(dir.join(INDEX_FILE)).unwrap();
+        let entry = index.get("1", Stage::Video).unwrap();
+        assert_eq!(
+            (entry.size, entry.checksum.clone()),
+            checksum(&video).unwrap()
+        );
+        assert_ne!(
+            entry.checksum,
+            index.get("1", Stage::Json).unwrap().checksum
+        );
+        fs::write(&video, "changed").unwrap();
+        assert_ne!(entry.checksum, checksum(&video).unwrap().1);
+        assert_eq!(
+            index.get("1", Stage::Json).unwrap().checksum,
+            checksum(&json).unwrap().1
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn failures_do_not_replace_complete_stages() {
+        let dir = temp_dir("index-state");
+        let json = dir.join("1.json");
+        fs::write(&json, "{}").unwrap();
+        let mut index = Index::load(dir.join(INDEX_FILE)).unwrap();
+        index
+            .insert(Entry::complete("1", Stage::Json, &json).unwrap())
+            .unwrap();
+        index.fail("1", Stage::Json, &json).unwrap();
+        index.fail("1", Stage::Video, &dir.join("1.mp4")).unwrap();
+        assert!(index.is_complete("1", Stage::Json));
+        assert_eq!(index.get("1", Stage::Video).unwrap().state, State::Failed);
+        assert!(index.is_archived("1", &[Stage::Json]));
+        assert!(!index.is_archived("1", &[Stage::Json, Stage::Video]));
+        assert!(!index.is_archived("2", &[Stage::Json]));
+
+        index.redo("1", Stage::Json, &json).unwrap();
+        assert!(!index.is_complete("1", Stage::Json));
+        let index = Index::load(dir.join(INDEX_FILE)).unwrap();
+        assert_eq!(index.get("1", Stage::Json).unwrap().state, State::Failed);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn load_compacts_replaced_entries() {
+        let dir = temp_dir("index-compact");
+        let path = dir.join(INDEX_FILE);
+        let json = dir.join("1.json");
+        fs::write(&json, "{}").unwrap();
+        let mut index = Index::load(&path).unwrap();
+        index.fail("1", Stage::Json, &json).unwrap();
+        index.fail("1", Stage::Video, &dir.join("1.mp4")).unwrap();
+        index
+            .insert(Entry::complete("1", Stage::Json, &json).unwrap())
+            .unwrap();
+        assert_eq!(lines(&path), 3);
+
+        let mut index = Index::load(&path).unwrap();
+        assert_eq!(lines(&path), 2);
+        assert!(index.is_complete("1", Stage::Json));
+        assert!(!index.path.with_extension("jsonl.tmp").exists());
+        index.compact().unwrap();
+        assert_eq!(lines(&path), 2);
+        index.redo("1", Stage::Video, &dir.join("1.mp4")).unwrap();
+        index.compact().unwrap();
+        assert_eq!(lines(&path), 2);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
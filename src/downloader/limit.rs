@@ -0,0 +1,69 @@
+use super::utils::{lock, message};
+use super::Context;
+use reqwest::blocking::Response;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    remaining: u64,
+    reset: SystemTime,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RateLimit {
+    twitch: Mutex<Option<Window>>,
+    youtube_units: AtomicU64,
+}
+
+impl RateLimit {
+    pub(crate) fn wait(&self, context: &Context) {
+        let reset = match *lock(&self.twitch) {
+            Some(Window {
+                remaining: 0,
+                reset,
+            }) => reset,
+            _ => return,
+        };
+        if let Ok(delay) = reset.duration_since(SystemTime::now()) {
+            message(
+                &format!(
+                    "[rate_limit] Twitch rate limit reached, waiting {:.1}s for it to reset",
+                    delay.as_secs_f64()
+                ),
+                context,
+                1,
+            );
+            thread::sleep(delay);
+        }
+    }
+
+    pub(crate) fn update(&self, response: &Response) {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        let (Some(remaining), Some(reset)) =
+            (header("Ratelimit-Remaining"), header("Ratelimit-Reset"))
+        else {
+            return;
+        };
+        *lock(&self.twitch) = Some(Window {
+            remaining,
+            reset: UNIX_EPOCH + Duration::from_secs(reset),
+        });
+    }
+
+    pub(crate) fn spend(&self, units: u64) {
+        self.youtube_units.fetch_add(units, Ordering::Relaxed);
+    }
+
+    pub(crate) fn youtube_units(&self) -> u64 {
+        self.youtube_units.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,183 @@
+use super::common::{self, indexed, parse_result, video_filename};
+use super::index::Stage;
+use super::utils::{command, finish_part, loggers, part_path, remove_part, verified, VideoInfo};
+use super::{chat, retry, twitch, youtube, Context, External, VideoType};
+use crate::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Child;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long live chat may keep running on its own after the stream ended.
+const CHAT_GRACE: Duration = Duration::from_secs(30);
+
+pub(super) fn download<T: VideoInfo>(info: &T, context: &Context) -> Result<(), Error> {
+    let id = info.id();
+    let dir = common::directory(info, context)?;
+    fs::create_dir_all(&dir)?;
+    let chat = dir.join(format!("{id}.chat.json"));
+    let video_title = video_filename(info, &context.downloader);
+    let video = dir.join(&video_title);
+
+    let recorder =
+        match context.index().is_complete(id, Stage::Chat) || context.compression.exists(&chat) {
+            true => None,
+            false => Some(record_chat(info, &dir, context)),
+        };
+
+    let spinner_text = format!(" Recording {video_title}");
+    context.spinner.create(&spinner_text);
+    let result = indexed(id, Stage::Video, &video, context, |context| {
+        record_video(info, &dir, context)
+    });
+    context.spinner.end();
+    parse_result(&result, context, "video", "Record", &video);
+
+    let spinner_text = format!(" Recording {id}.chat.json");
+    context.spinner.create(&spinner_text);
+    let result = indexed(id, Stage::Chat, &chat, context, |_| match recorder {
+        Some(child) => stop_chat(child?, &chat),
+        None => Err(Error::AlreadyExists),
+    });
+    context.spinner.end();
+    parse_result(&result, context, "chat", "Record", &chat);
+
+    match context.downloader {
+        VideoType::YouTubeLive => common::download(
+            info,
+            context,
+            youtube::save_json,
+            common::get_thumbnail,
+            recorded_chat,
+            chat::process,
+            recorded_video,
+        ),
+        _ => common::download(
+            info,
+            context,
+            common::save_json,
+            common::get_thumbnail,
+            recorded_chat,
+            chat::process,
+            recorded_video,
+        ),
+    }
+}
+
+pub(super) fn get_channel_ids<T: VideoInfo>(
+    channel: &str,
+    context: &Context,
+) -> Result<Vec<T>, Error> {
+    match context.downloader {
+        VideoType::YouTubeLive => youtube::get_live_ids(channel, context),
+        _ => twitch::get_live_ids(channel, context),
+    }
+}
+
+fn url<T: VideoInfo>(info: &T, context: &Context) -> String {
+    match context.downloader {
+        VideoType::YouTubeLive => format!("https://www.youtube.com/watch?v={}", info.id()),
+        _ => format!("https://www.twitch.tv/{}", info.to_video().user_login),
+    }
+}
+
+fn record_chat<T: VideoInfo>(info: &T, dir: &Path, context: &Context) -> Result<Child, Error> {
+    let id = info.id();
+    let chat_string = format!("{id}.chat.json");
+    if context.missing.contains(&External::Cd) {
+        return Err(Error::MissingProgram(External::Cd));
+    }
+    remove_part(dir.join(&chat_string))?;
+    let (log, err_log) = loggers(dir.join(format!("{id}.chat")), context.logging);
+    Ok(command(&External::Cd, dir)
+        .args([&url(info, context), "--output"])
+        .arg(part_path(&chat_string))
+        .stdout(log)
+        .stderr(err_log)
+        .spawn()?)
+}
+
+fn stop_chat(mut child: Child, chat: &Path) -> Result<(), Error> {
+    let deadline = Instant::now() + CHAT_GRACE;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            interrupt(&mut child);
+            child.wait()?;
+            break;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    finish_part(chat)
+}
+
+#[cfg(unix)]
+fn interrupt(child: &mut Child) {
+    match libc::pid_t::try_from(child.id()) {
+        // SIGINT lets chat_downloader close the JSON it is writing
+        Ok(pid) => unsafe {
+            libc::kill(pid, libc::SIGINT);
+        },
+        Err(_) => {
+            let _ = child.kill();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn interrupt(child: &mut Child) {
+    let _ = child.kill();
+}
+
+fn record_video<T: VideoInfo>(info: &T, dir: &Path, context: &Context) -> Result<(), Error> {
+    let video_title = video_filename(info, &context.downloader);
+    let video = &dir.join(&video_title);
+    if verified(video) {
+        return Err(Error::AlreadyExists);
+    }
+    if context.missing.contains(&External::YtDlp) {
+        return Err(Error::MissingProgram(External::YtDlp));
+    }
+    let (log, err_log) = loggers(dir.join(format!("{}.video", info.id())), context.logging);
+    let output = part_path(&video_title).to_string_lossy().replace('%', "%%");
+    let mut yt_dlp = command(&External::YtDlp, dir);
+    // yt-dlp only supports recording from the start of YouTube streams
+    if matches!(context.downloader, VideoType::YouTubeLive) {
+        yt_dlp.arg("--live-from-start");
+    }
+    retry::status(
+        External::YtDlp,
+        yt_dlp
+            .args([
+                "-N",
+                &context.threads.to_string(),
+                "--merge-output-format",
+                "mp4",
+                "-o",
+                &output,
+                &url(info, context),
+            ])
+            .stdout(log)
+            .stderr(err_log),
+        context,
+    )?;
+    finish_part(video)
+}
+
+fn recorded_chat(id: &str, dir: &Path, context: &Context) -> Result<(), Error> {
+    match context
+        .compression
+        .exists(&dir.join(format!("{id}.chat.json")))
+    {
+        true => Err(Error::AlreadyExists),
+        false => Err(Error::NoChatFound),
+    }
+}
+
+fn recorded_video<T: VideoInfo>(info: &T, dir: &Path, context: &Context) -> Result<(), Error> {
+    let video = dir.join(video_filename(info, &context.downloader));
+    match verified(&video) {
+        true => Err(Error::AlreadyExists),
+        false => Err(Error::Incomplete(video)),
+    }
+}
@@ -0,0 +1,96 @@
+use super::chapters::Chapter;
+use super::twitch::Moment;
+use super::utils::write_file;
+use crate::Error;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// Version of the `{id}.json` layout, increased whenever a field changes meaning.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+/// The `{id}.json` saved for every video: the fields all platforms share, with
+/// the same meaning everywhere, and the API response they came from in `extra`.
+#[derive(Debug, Serialize)]
+pub(crate) struct VideoMetadata {
+    pub(crate) schema_version: u32,
+    /// `twitch` or `youtube`
+    pub(crate) platform: &'static str,
+    /// `archive`, `highlight`, `upload`, `live` or `clip` on Twitch, `video` on YouTube
+    pub(crate) kind: String,
+    pub(crate) id: String,
+    pub(crate) title: String,
+    pub(crate) description: Option<String>,
+    pub(crate) channel_id: String,
+    /// Login name of Twitch channels
+    pub(crate) channel_login: Option<String>,
+    pub(crate) channel_name: String,
+    pub(crate) created_at: String,
+    pub(crate) published_at: Option<String>,
+    pub(crate) url: String,
+    pub(crate) thumbnail_url: String,
+    /// Seconds, unknown for live streams
+    pub(crate) duration: Option<f64>,
+    /// Views of the video, unknown for live streams
+    pub(crate) view_count: Option<u64>,
+    pub(crate) language: Option<String>,
+    pub(crate) chapters: Vec<Chapter>,
+    /// Games played during Twitch VODs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) moments: Option<Vec<Moment>>,
+    /// The whole API response the video was read from
+    pub(crate) extra: Value,
+}
+
+impl VideoMetadata {
+    pub(crate) fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        write_file(path, json.as_bytes())
+    }
+}
+
+/// Fields read from the `{id}.json` of any platform, including the ones
+/// saved before [`VideoMetadata`].
+#[derive(Debug, Default)]
+pub(crate) struct Metadata {
+    pub(crate) title: Option<String>,
+    pub(crate) channel: Option<String>,
+    pub(crate) date: Option<String>,
+    pub(crate) url: Option<String>,
+}
+
+impl Metadata {
+    /// Reads the JSON saved for Twitch videos, clips and YouTube videos, in any schema.
+    pub(crate) fn read(path: &Path) -> Self {
+        let value = fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok());
+        let Some(value) = value else {
+            return Self::default();
+        };
+        let field = |pointers: &[&str]| {
+            pointers
+                .iter()
+                .find_map(|pointer| value.pointer(pointer)?.as_str())
+                .map(str::to_string)
+        };
+        Self {
+            title: field(&["/title", "/snippet/title"]),
+            channel: field(&[
+                "/channel_name",
+                "/user_name",
+                "/broadcaster_name",
+                "/snippet/channelTitle",
+            ]),
+            date: field(&["/created_at", "/snippet/publishedAt"]),
+            url: field(&["/url"]).or_else(|| {
+                value
+                    .pointer("/snippet")
+                    .and(value.pointer("/id")?.as_str())
+                    .map(|id| format!("https://www.youtube.com/watch?v={id}"))
+            }),
+        }
+    }
+}
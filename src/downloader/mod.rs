@@ -1,19 +1,43 @@
+pub(super) mod chapters;
+pub(super) mod chat;
 pub(super) mod clip;
-mod common;
+pub(super) mod common;
+pub(super) mod compress;
+pub(super) mod emotes;
 pub(super) mod error;
+pub(super) mod filter;
+pub(super) mod gql;
 mod highlight;
+mod html;
+pub(super) mod index;
+pub(super) mod limit;
+mod live;
+pub(super) mod metadata;
+pub(super) mod muted;
+mod refresh;
+pub(super) mod remux;
+pub(super) mod report;
+pub(super) mod retry;
+mod search;
+mod stats;
 pub(super) mod twitch;
 pub(super) mod utils;
 mod vod;
 pub(super) mod youtube;
 use crate::init::{external::External, Context, VideoType, Videos};
 use crate::Error;
+use index::Stage;
 use utils::VideoInfo;
 
-use self::utils::{help_error, message};
+use self::report::Report;
+use self::utils::{error_msg, help_error, lock, message, split_videos};
+use chrono::Duration;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::thread;
 
 impl Videos {
-    pub(super) fn download<T: VideoInfo>(&self, context: &mut Context) {
+    pub(super) fn download<T: VideoInfo>(&self, context: &Context) -> u8 {
         let (platform, ids): (&VideoType, Result<Vec<T>, Error>) = match self {
             Videos::Direct(info) => (
                 &info.platform,
@@ -27,42 +51,245 @@ impl Videos {
         let ids = match ids {
             Ok(ids) => ids,
             Err(err) => {
-                println!("{err}");
-                return;
-            } //todo!() Add error message
+                error_msg(
+                    Some("input"),
+                    format!("Could not get videos: {}", err.describe()),
+                    context,
+                );
+                context.report().fail(None, &err);
+                return finish(context);
+            }
         };
-        for info in ids {
-            match platform.download(&info, context) {
-                Ok(()) => {}
-                Err(err) => {
-                    // todo!() Maybe count errors?
-                    message(&err.to_string(), context, -1);
+        if context.reindex {
+            let total = ids.len();
+            let mut failed = 0;
+            for info in ids {
+                if let Err(err) = common::reindex(&info, context) {
+                    message(
+                        &format!("Could not reindex {}: {}", info.id(), err.describe()),
+                        context,
+                        -1,
+                    );
+                    failed += 1;
+                }
+            }
+            if let Err(err) = context.index().save() {
+                message(
+                    &format!("Could not save index: {}", err.describe()),
+                    context,
+                    -1,
+                );
+                return report::TOTAL_FAILURE;
+            }
+            return match failed {
+                0 => report::SUCCESS,
+                failed if failed == total => report::TOTAL_FAILURE,
+                _ => report::PARTIAL_FAILURE,
+            };
+        }
+        archive_all(platform, ids, context);
+        finish(context)
+    }
+
+    /// Archives the videos that appear on the channels after the first poll, or
+    /// every video when `backfill`. Streams are recorded from the first poll.
+    pub(super) fn watch<T: VideoInfo>(
+        &self,
+        every: Duration,
+        backfill: bool,
+        context: &Context,
+    ) -> ! {
+        let (Videos::Channel(info) | Videos::Direct(info)) = self;
+        let channels = split_videos(&info.data);
+        let every = every.to_std().unwrap_or_default();
+        let live = matches!(
+            info.platform,
+            VideoType::TwitchLive | VideoType::YouTubeLive
+        );
+        let platform = &info.platform;
+        let mut seen = HashSet::new();
+        let mut listed = HashSet::new();
+        // Streams being recorded, which are polled for again once they are done
+        let recording = Mutex::new(HashSet::new());
+        thread::scope(|scope| loop {
+            for channel in &channels {
+                let ids: Vec<T> = match platform.get_channel_ids(channel, context) {
+                    Ok(ids) => ids,
+                    Err(err) => {
+                        error_msg(
+                            Some("watch"),
+                            format!("Could not get videos of {channel}: {}", err.describe()),
+                            context,
+                        );
+                        continue;
+                    }
+                };
+                if live {
+                    // Recordings last as long as the stream, so the other channels keep being polled
+                    for info in ids {
+                        if !lock(&recording).insert(info.id().to_string()) {
+                            continue;
+                        }
+                        let recording = &recording;
+                        scope.spawn(move || {
+                            archive(platform, &info, context);
+                            compact(context);
+                            let mut report = context.report().take(info.id());
+                            summarize(&mut report, context);
+                            lock(recording).remove(info.id());
+                        });
+                    }
+                    continue;
+                }
+                if listed.insert(channel) && !backfill {
+                    message(
+                        &format!(
+                            "[watch] Skipping the {} videos already on {channel}, use --backfill to archive them",
+                            ids.len()
+                        ),
+                        context,
+                        0,
+                    );
+                    seen.extend(ids.iter().map(|info| info.id().to_string()));
+                    continue;
+                }
+                let (known, mut ids): (Vec<T>, Vec<T>) =
+                    ids.into_iter().partition(|info| seen.contains(info.id()));
+                message(
+                    &format!("[watch] Found {} new videos of {channel}", ids.len()),
+                    context,
+                    1,
+                );
+                let found: Vec<String> = ids.iter().map(|info| info.id().to_string()).collect();
+                if matches!(platform, VideoType::Vod | VideoType::Highlight) {
+                    // Archived again to remux the changed muted segments into them
+                    ids.extend(known.into_iter().filter(|info| muted::check(info, context)));
+                }
+                archive_all(platform, ids, context);
+                let report = context.report();
+                seen.extend(found.into_iter().filter(|id| !report.is_failed(id)));
+            }
+            if !live {
+                if !context.report().videos.is_empty() {
+                    finish(context);
                 }
+                *context.report() = Report::default();
+            }
+            message(
+                &format!("[watch] Polling again in {}s", every.as_secs()),
+                context,
+                1,
+            );
+            thread::sleep(every);
+        })
+    }
+}
+
+fn archive_all<T: VideoInfo>(platform: &VideoType, ids: Vec<T>, context: &Context) {
+    let workers = context.jobs.min(ids.len());
+    let queue = Mutex::new(ids.into_iter());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let Some(info) = lock(&queue).next() else {
+                    break;
+                };
+                archive(platform, &info, context);
+            });
+        }
+    });
+}
+
+fn finish(context: &Context) -> u8 {
+    compact(context);
+    summarize(&mut context.report(), context)
+}
+
+fn compact(context: &Context) {
+    if let Err(err) = context.index().compact() {
+        error_msg(
+            Some("index"),
+            format!("Could not compact index: {}", err.describe()),
+            context,
+        );
+    }
+}
+
+/// Prints the summary of `report` and writes it to `--report`, returning the exit code.
+fn summarize(report: &mut Report, context: &Context) -> u8 {
+    report.youtube_quota_units = context.rate_limit.youtube_units();
+    let code = report.finish(context.fail_on_missing);
+    if !report.videos.is_empty() || !report.missing.is_empty() {
+        message(&report.table(), context, 0);
+    }
+    if let Some(path) = &context.report_path {
+        if let Err(err) = report.write(path) {
+            error_msg(
+                Some("report"),
+                format!("Could not write report: {}", err.describe()),
+                context,
+            );
+        }
+    }
+    code
+}
+
+fn archive<T: VideoInfo>(platform: &VideoType, info: &T, context: &Context) {
+    context.report().start(info.id(), info.title());
+    if matches!(platform, VideoType::Vod | VideoType::Highlight) {
+        muted::check(info, context);
+    }
+    let stages = stages(info, context);
+    if context.index().is_archived(info.id(), &stages) {
+        if let Ok(artifacts) = common::artifacts(info, context) {
+            let mut report = context.report();
+            for (stage, path) in artifacts.iter().filter(|(stage, _)| stages.contains(stage)) {
+                report.record(info.id(), *stage, path, &Err(Error::AlreadyArchived));
             }
         }
+        message(
+            &format!("[index] Skipping {}, already archived", info.id()),
+            context,
+            1,
+        );
+        return;
+    }
+    match platform.download(info, context) {
+        Ok(()) | Err(Error::Stage { .. }) => {}
+        Err(err) => {
+            message(&err.describe(), context, -1);
+            context.report().fail(Some(info.id()), &err);
+        }
     }
 }
 
+fn stages<T: VideoInfo>(info: &T, context: &Context) -> Vec<Stage> {
+    common::artifacts(info, context)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(stage, _)| stage)
+        .filter(|stage| !(context.skip_video && matches!(stage, Stage::Video | Stage::Remux)))
+        .collect()
+}
+
 impl VideoType {
-    fn download<T: VideoInfo>(&self, info: &T, context: &mut Context) -> Result<(), Error> {
+    fn download<T: VideoInfo>(&self, info: &T, context: &Context) -> Result<(), Error> {
         match self {
             VideoType::Vod => vod::download(info, context),
             VideoType::Highlight => highlight::download(info, context),
             VideoType::Clip => clip::download(info, context),
             VideoType::YouTube => youtube::download(info, context),
+            VideoType::TwitchLive | VideoType::YouTubeLive => live::download(info, context),
         }
     }
-    fn get_videos_ids<T: VideoInfo>(
-        &self,
-        data: &str,
-        context: &mut Context,
-    ) -> Result<Vec<T>, Error> {
+    fn get_videos_ids<T: VideoInfo>(&self, data: &str, context: &Context) -> Result<Vec<T>, Error> {
         context.spinner.create(" Getting data from IDs");
         let info = match self {
             VideoType::Vod => vod::get_ids(data, context),
             VideoType::Highlight => highlight::get_ids(data, context),
             VideoType::Clip => clip::get_ids(data, context),
             VideoType::YouTube => youtube::get_ids(data, context),
+            VideoType::TwitchLive | VideoType::YouTubeLive => live::get_channel_ids(data, context),
         };
         context.spinner.end();
         match info {
@@ -83,7 +310,7 @@ impl VideoType {
     fn get_channel_ids<T: VideoInfo>(
         &self,
         channel: &str,
-        context: &mut Context,
+        context: &Context,
     ) -> Result<Vec<T>, Error> {
         context.spinner.create(" Getting channel data");
         let info = match self {
@@ -91,6 +318,9 @@ impl VideoType {
             VideoType::Highlight => highlight::get_channel_ids(channel, context),
             VideoType::Clip => clip::get_channel_ids(channel, context),
             VideoType::YouTube => youtube::get_channel_ids(channel, context),
+            VideoType::TwitchLive | VideoType::YouTubeLive => {
+                live::get_channel_ids(channel, context)
+            }
         };
         context.spinner.end();
         info
@@ -0,0 +1,194 @@
+use super::chapters;
+use super::chat::timestamp;
+use super::common::directory;
+use super::index::Stage;
+use super::twitch::MutedSegments;
+use super::utils::{error_msg, verified, warn_msg, write_file, VideoInfo};
+use super::Context;
+use crate::Error;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Muted {
+    /// Mark the muted segments in the chapters embedded by remuxing
+    pub(crate) embed: bool,
+}
+
+/// Muted segments of a VOD, written to `{id}.muted.json`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Sidecar {
+    pub(crate) segments: Vec<MutedSegments>,
+    /// When the segments were first seen, the file is only written again once they change
+    pub(crate) checked_at: String,
+    /// Segments seen by earlier runs before they changed, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) history: Vec<Previous>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Previous {
+    pub(crate) segments: Vec<MutedSegments>,
+    pub(crate) checked_at: String,
+}
+
+/// Records the muted segments of a VOD in the report and in `{id}.muted.json`
+/// and `{id}.muted.ffmetadata`, warning when they changed since the last run.
+/// Runs even for archived videos, so changes are noticed when watching.
+/// Returns whether the video has to be remuxed again to embed the new segments.
+pub(super) fn check<T: VideoInfo>(info: &T, context: &Context) -> bool {
+    let id = info.id();
+    update(info, context).unwrap_or_else(|err| {
+        error_msg(
+            Some("muted"),
+            format!(
+                "Could not record muted segments of {id}: {}",
+                err.describe()
+            ),
+            context,
+        );
+        false
+    })
+}
+
+fn update<T: VideoInfo>(info: &T, context: &Context) -> Result<bool, Error> {
+    let id = info.id();
+    let video = info.to_video();
+    let segments = video.muted_segments.unwrap_or_default();
+    let dir = directory(info, context)?;
+    let path = dir.join(format!("{id}.muted.json"));
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut sidecar = match verified(&path) {
+        true => {
+            let bytes = fs::read(&path).map_err(|err| Error::io(err, &path))?;
+            serde_json::from_slice(&bytes).map_err(|err| Error::json(err, &path))?
+        }
+        false => Sidecar::default(),
+    };
+    let exists = verified(&path);
+    let changed = exists && sidecar.segments != segments;
+    context.report().muted(id, info.title(), &segments, changed);
+    if exists && !changed {
+        return Ok(false);
+    }
+    if changed {
+        warn_msg(
+            Some("muted"),
+            format!(
+                "Muted segments of {id} changed since archiving: {} → {}",
+                describe(&sidecar.segments),
+                describe(&segments)
+            ),
+            context,
+        );
+        sidecar.history.push(Previous {
+            segments: std::mem::take(&mut sidecar.segments),
+            checked_at: sidecar.checked_at.clone(),
+        });
+    }
+    sidecar.segments = segments;
+    sidecar.checked_at = now;
+    fs::create_dir_all(&dir).map_err(|err| Error::io(err, &dir))?;
+    let mut json = serde_json::to_string_pretty(&sidecar)?;
+    json.push('\n');
+    write_file(&path, json.as_bytes())?;
+    let remux = changed && context.muted.embed && context.remux.enabled;
+    if remux {
+        // The remux is skipped while its metadata exists
+        let metadata = dir.join(format!("{id}.ffmetadata"));
+        if metadata.exists() {
+            fs::remove_file(&metadata).map_err(|err| Error::io(err, &metadata))?;
+        }
+        context.index().redo(id, Stage::Remux, &metadata)?;
+    }
+    let sidecar_chapters = dir.join(format!("{id}.muted.ffmetadata"));
+    if sidecar.segments.is_empty() {
+        if sidecar_chapters.exists() {
+            fs::remove_file(&sidecar_chapters).map_err(|err| Error::io(err, &sidecar_chapters))?;
+        }
+        return Ok(remux);
+    }
+    let metadata = chapters::ffmetadata(
+        &[],
+        &chapters::timeline(
+            &[],
+            &ranges(&sidecar.segments),
+            chapters::seconds(&video.duration),
+        ),
+    )?;
+    write_file(&sidecar_chapters, metadata.as_bytes())?;
+    Ok(remux)
+}
+
+/// The start and end of every muted segment, in seconds.
+pub(super) fn ranges(segments: &[MutedSegments]) -> Vec<(f64, f64)> {
+    #[allow(clippy::cast_precision_loss)]
+    segments
+        .iter()
+        .map(|segment| {
+            (
+                segment.offset as f64,
+                (segment.offset + segment.duration) as f64,
+            )
+        })
+        .collect()
+}
+
+/// Muted ranges as `h:mm:ss-h:mm:ss`, separated by commas.
+pub(crate) fn describe(segments: &[MutedSegments]) -> String {
+    if segments.is_empty() {
+        return "none".to_string();
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let ranges: Vec<String> = segments
+        .iter()
+        .map(|segment| {
+            format!(
+                "{}-{}",
+                timestamp(segment.offset as f64, "", 0, false),
+                timestamp((segment.offset + segment.duration) as f64, "", 0, false)
+            )
+        })
+        .collect();
+    ranges.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::twitch::Video;
+    use super::super::utils::temp_dir;
+    use super::*;
+    use crate::init::endpoints::Endpoints;
+
+    #[test]
+    fn change_stays_reported_when_checked_again() {
+        let dir = temp_dir("muted-change");
+        let context = Context::test(Endpoints::default(), &dir);
+        check(&Video::test("1", &[(0, 30)]), &context);
+        assert!(!context
+            .report()
+            .videos
+            .iter()
+            .any(|video| video.muted_changed));
+
+        // Watching checks a known video, then archives it, which checks it again
+        let video = Video::test("1", &[(60, 30)]);
+        check(&video, &context);
+        context.report().start("1", video.title());
+        check(&video, &context);
+        let report = context.report();
+        assert!(report.videos[0].muted_changed);
+        assert_eq!(
+            report.videos[0].muted,
+            video.muted_segments.clone().unwrap()
+        );
+
+        let bytes = fs::read(dir.join("1.muted.json")).unwrap();
+        let sidecar: Sidecar = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(sidecar.history.len(), 1);
+        assert_eq!(sidecar.history[0].segments[0].offset, 0);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,508 @@
+use super::chapters::{self, Chapter};
+use super::clip::Clip;
+use super::index::{Entry, Stage};
+use super::search::walk;
+use super::twitch::{self, Video};
+use super::utils::{
+    api_message, colorize, error, error_msg, from_raw, good_msg, message, write_file, VideoInfo,
+};
+use super::youtube::{self, YtVideo};
+use crate::init::{Context, RefreshTask};
+use crate::report;
+use crate::Error;
+use chrono::{SecondsFormat, Utc};
+use colored::Color;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Most videos the YouTube Data API looks up in one request.
+const YOUTUBE_BATCH: usize = 50;
+/// Keys of the API responses in `extra` that change without the video
+/// changing, or whose change is already found in the top-level fields.
+const VOLATILE: [&str; 3] = ["etag", "statistics", "view_count"];
+
+/// The API an archived video is looked up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source {
+    Video,
+    Clip,
+    YouTube,
+}
+
+/// A video's `{id}.json` as it was last saved.
+#[derive(Debug)]
+struct Archived {
+    id: String,
+    path: PathBuf,
+    metadata: Map<String, Value>,
+}
+
+impl RefreshTask {
+    /// Looks up every video archived in `dirs` again, saving the metadata that
+    /// changed as a new revision and the videos that are no longer available.
+    /// Returns `PARTIAL_FAILURE` when a video could not be refreshed.
+    pub(crate) fn run(&self) -> u8 {
+        let mut files = Vec::new();
+        for dir in &self.dirs {
+            if let Err(err) = walk(dir, &mut files) {
+                error(&format!("Could not refresh: {}", err.describe()), None);
+            }
+        }
+        let mut failed = 0;
+        let mut sources: BTreeMap<Source, Vec<Archived>> = BTreeMap::new();
+        for path in files {
+            match archived(&path) {
+                Ok(Some((source, video))) => sources.entry(source).or_default().push(video),
+                Ok(None) => {}
+                Err(err) => {
+                    error(
+                        &format!("Could not read {}: {}", path.display(), err.describe()),
+                        None,
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        let (mut refreshed, mut changed, mut unavailable) = (0, 0, 0);
+        for (source, videos) in sources {
+            let context = match source {
+                Source::Video | Source::Clip => &self.twitch,
+                Source::YouTube => &self.youtube,
+            };
+            let context = match context {
+                Ok(context) => context,
+                Err(err) => {
+                    error(
+                        &format!(
+                            "Could not refresh {} {source}: {}",
+                            videos.len(),
+                            err.describe()
+                        ),
+                        None,
+                    );
+                    failed += videos.len();
+                    continue;
+                }
+            };
+            let ids: BTreeSet<&str> = videos.iter().map(|video| video.id.as_str()).collect();
+            context
+                .spinner
+                .create(&format!(" Looking up {} {source}", ids.len()));
+            let found = lookup(source, &ids.into_iter().collect::<Vec<_>>(), context);
+            context.spinner.end();
+            let found = match found {
+                Ok(found) => found,
+                Err(err) => {
+                    error_msg(
+                        Some("refresh"),
+                        format!("Could not look up {source}: {}", err.describe()),
+                        context,
+                    );
+                    failed += videos.len();
+                    continue;
+                }
+            };
+            for video in videos {
+                let title = video
+                    .metadata
+                    .get("title")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                let result = match found.get(&video.id) {
+                    Some(raw) => update(source, &video, raw.clone(), context),
+                    None => {
+                        unavailable += 1;
+                        gone(&video, context)
+                    }
+                };
+                match result {
+                    Ok(fields) if fields.is_empty() => {
+                        message(&format!("[refresh] {} is unchanged", video.id), context, 1);
+                    }
+                    Ok(_) if !found.contains_key(&video.id) => {
+                        // Unlike other warnings, shown without --verbose
+                        let warning = format!(
+                            "{} {title} is no longer available, it was deleted or made private",
+                            video.id
+                        );
+                        message(
+                            &colorize(Some("refresh"), &warning, Color::BrightYellow),
+                            context,
+                            0,
+                        );
+                    }
+                    Ok(fields) => {
+                        changed += 1;
+                        good_msg(
+                            Some("refresh"),
+                            format!("{} {title}: {} changed", video.id, fields.join(", ")),
+                            context,
+                        );
+                    }
+                    Err(err) => {
+                        error_msg(
+                            Some("refresh"),
+                            format!("Could not refresh {}: {}", video.id, err.describe()),
+                            context,
+                        );
+                        failed += 1;
+                        continue;
+                    }
+                }
+                refreshed += 1;
+            }
+        }
+        if self.verbosity >= 0 {
+            println!(
+                "Refreshed {refreshed} videos, {changed} changed, {unavailable} no longer available, {failed} failed"
+            );
+        }
+        match failed {
+            0 => report::SUCCESS,
+            _ => report::PARTIAL_FAILURE,
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Source::Video => write!(f, "Twitch videos"),
+            Source::Clip => write!(f, "Twitch clips"),
+            Source::YouTube => write!(f, "YouTube videos"),
+        }
+    }
+}
+
+/// Reads `path` if it is the `{id}.json` of a video that can be looked up again.
+fn archived(path: &Path) -> Result<Option<(Source, Archived)>, Error> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let Some(id) = name.strip_suffix(".json").filter(|id| !id.contains('.')) else {
+        return Ok(None);
+    };
+    let bytes = fs::read(path).map_err(|err| Error::io(err, path))?;
+    let value: Value = serde_json::from_slice(&bytes).map_err(|err| Error::json(err, path))?;
+    let Value::Object(metadata) = value else {
+        return Ok(None);
+    };
+    if metadata.get("id").and_then(Value::as_str) != Some(id) {
+        return Ok(None);
+    }
+    Ok(source(&metadata).map(|source| {
+        let video = Archived {
+            id: id.to_string(),
+            path: path.to_path_buf(),
+            metadata,
+        };
+        (source, video)
+    }))
+}
+
+/// The API of a video saved in any schema, none for live streams, whose ids
+/// are not video ids.
+fn source(metadata: &Map<String, Value>) -> Option<Source> {
+    let field = |key: &str| metadata.get(key).and_then(Value::as_str);
+    match (field("platform"), field("kind").or(field("type"))) {
+        (_, Some("live")) => None,
+        (Some("youtube"), _) | (None, Some("youtube")) => Some(Source::YouTube),
+        (_, Some("clip")) => Some(Source::Clip),
+        (None, None) if metadata.contains_key("snippet") => Some(Source::YouTube),
+        (None, None) if metadata.contains_key("broadcaster_id") => Some(Source::Clip),
+        (Some("twitch"), _) | (None, Some(_)) => Some(Source::Video),
+        _ => None,
+    }
+}
+
+/// The API responses of the videos among `ids` that still exist, by id.
+fn lookup(
+    source: Source,
+    ids: &[&str],
+    context: &Context,
+) -> Result<HashMap<String, Value>, Error> {
+    let mut found = HashMap::new();
+    match source {
+        Source::Video | Source::Clip => {
+            let endpoint = match source {
+                Source::Clip => "clips",
+                _ => "videos",
+            };
+            collect(&twitch::lookup(endpoint, ids, context)?, &mut found);
+        }
+        Source::YouTube => {
+            for batch in ids.chunks(YOUTUBE_BATCH) {
+                let url = format!(
+                    "https://youtube.googleapis.com/youtube/v3/videos?part=snippet%2CcontentDetails%2Cstatistics&id={}&maxResults={YOUTUBE_BATCH}&key={}",
+                    batch.join("%2C"),
+                    context.token
+                );
+                let response: Value = serde_json::from_str(&youtube::get(&url, context)?)?;
+                let Some(items) = response.get("items").and_then(Value::as_array) else {
+                    return Err(Error::Api(api_message(&response)));
+                };
+                collect(items, &mut found);
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn collect(items: &[Value], found: &mut HashMap<String, Value>) {
+    for item in items {
+        if let Some(id) = item.get("id").and_then(Value::as_str) {
+            found.insert(id.to_string(), item.clone());
+        }
+    }
+}
+
+/// Saves the metadata of `raw` if it differs from the archived one, keeping
+/// the chapters and game changes added while archiving. Returns the fields
+/// that changed, or only `schema_version` when converting an older layout.
+fn update(
+    source: Source,
+    video: &Archived,
+    raw: Value,
+    context: &Context,
+) -> Result<Vec<String>, Error> {
+    let mut metadata = match source {
+        Source::Video => from_raw::<Video>(raw)?.metadata(),
+        Source::Clip => from_raw::<Clip>(raw)?.metadata(),
+        Source::YouTube => from_raw::<YtVideo>(raw)?.metadata(),
+    };
+    let mut chapters: Vec<Chapter> = video
+        .metadata
+        .get("chapters")
+        .and_then(|chapters| serde_json::from_value(chapters.clone()).ok())
+        .unwrap_or_default();
+    let description = match source {
+        Source::YouTube => {
+            let description = chapters::from_description(
+                metadata.description.as_deref().unwrap_or_default(),
+                metadata.duration,
+            );
+            chapters.retain(|chapter| chapter.source != "description");
+            chapters.extend(description.iter().cloned());
+            chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+            Some(description)
+        }
+        _ => None,
+    };
+    metadata.chapters = chapters;
+    metadata.moments = video
+        .metadata
+        .get("moments")
+        .and_then(|moments| serde_json::from_value(moments.clone()).ok());
+    let refreshed: Map<String, Value> = serde_json::from_value(serde_json::to_value(&metadata)?)?;
+    let fields = match video.metadata.contains_key("schema_version") {
+        true => changed(&video.metadata, &refreshed),
+        false => vec!["schema_version".to_string()],
+    };
+    if fields.is_empty() {
+        return Ok(fields);
+    }
+    save(video, &refreshed, context)?;
+    if let Some(description) = description.filter(|_| fields.iter().any(|f| f == "description")) {
+        let dir = video.path.parent().unwrap_or(Path::new(""));
+        chapters::sidecars(dir, &video.id, &description, metadata.duration)?;
+    }
+    Ok(fields)
+}
+
+/// Marks a video that the API no longer returns with `unavailable_since`,
+/// returning it as the changed field the first time.
+fn gone(video: &Archived, context: &Context) -> Result<Vec<String>, Error> {
+    if video.metadata.contains_key("unavailable_since") {
+        return Ok(Vec::new());
+    }
+    let mut metadata = video.metadata.clone();
+    metadata.insert("unavailable_since".to_string(), json!(now()));
+    save(video, &metadata, context)?;
+    Ok(vec!["unavailable_since".to_string()])
+}
+
+/// The fields of `new` that differ from `old`, followed by the ones removed,
+/// with the keys of the API response in `extra` compared one by one as `extra.{key}`.
+fn changed(old: &Map<String, Value>, new: &Map<String, Value>) -> Vec<String> {
+    match (
+        old.get("extra").and_then(Value::as_object),
+        new.get("extra").and_then(Value::as_object),
+    ) {
+        (Some(old_extra), Some(new_extra)) => differences(old, new, &["extra"])
+            .into_iter()
+            .chain(
+                differences(old_extra, new_extra, &VOLATILE)
+                    .into_iter()
+                    .map(|key| format!("extra.{key}")),
+            )
+            .collect(),
+        _ => differences(old, new, &[]),
+    }
+}
+
+/// The keys of `new` whose values differ from `old`, followed by the ones
+/// removed, leaving out `skip`.
+fn differences(old: &Map<String, Value>, new: &Map<String, Value>, skip: &[&str]) -> Vec<String> {
+    let compared = |key: &&String| !skip.contains(&key.as_str());
+    new.iter()
+        .filter(|(key, value)| compared(key) && old.get(*key) != Some(value))
+        .map(|(key, _)| key)
+        .chain(
+            old.keys()
+                .filter(|key| compared(key) && !new.contains_key(*key)),
+        )
+        .cloned()
+        .collect()
+}
+
+/// Appends the archived revision to `{id}.history.jsonl` and replaces it with
+/// `metadata`, updating the checksum of the index if it has one.
+fn save(video: &Archived, metadata: &Map<String, Value>, context: &Context) -> Result<(), Error> {
+    let history = video
+        .path
+        .with_file_name(format!("{}.history.jsonl", video.id));
+    let mut line = serde_json::to_vec(&json!({
+        "replaced_at": now(),
+        "metadata": video.metadata,
+    }))?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&history)
+        .and_then(|mut file| file.write_all(&line))
+        .map_err(|err| Error::io(err, &history))?;
+    let mut json = serde_json::to_string_pretty(metadata)?;
+    json.push('\n');
+    write_file(&video.path, json.as_bytes())?;
+    let mut index = context.index();
+    if let Some(indexed) = index.get(&video.id, Stage::Json).cloned() {
+        let entry = Entry::complete(&video.id, Stage::Json, &video.path)?;
+        index.insert(Entry {
+            path: indexed.path,
+            ..entry
+        })?;
+    }
+    Ok(())
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::{serve, temp_dir};
+    use super::*;
+    use crate::init::endpoints::Endpoints;
+
+    /// The Helix response of a video, as it is also kept in `extra`.
+    fn raw(id: &str, title: &str) -> Value {
+        let mut video = Video::test(id, &[]);
+        video.title = title.to_string();
+        serde_json::to_value(&video).unwrap()
+    }
+
+    fn history(dir: &Path, id: &str) -> Vec<Value> {
+        fs::read_to_string(dir.join(format!("{id}.history.jsonl")))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn refresh_saves_changed_and_missing_videos() {
+        let dir = temp_dir("refresh");
+        for id in ["1", "2", "3"] {
+            let mut video = Video::test(id, &[]);
+            video.set_raw(raw(id, &format!("Video {id}")));
+            video
+                .metadata()
+                .write(&dir.join(format!("{id}.json")))
+                .unwrap();
+        }
+        let mut renamed = raw("1", "Renamed");
+        renamed["view_count"] = json!(100);
+        // Only a key that changes on its own
+        let mut tagged = raw("3", "Video 3");
+        tagged["etag"] = json!("abc");
+        let not_found = json!({ "error": "Not Found", "status": 404, "message": "" });
+        let data = |video: Value| json!({ "data": [video] }).to_string();
+        let (url, server) = serve(vec![
+            not_found.to_string(),
+            data(renamed),
+            not_found.to_string(),
+            data(tagged),
+        ]);
+        let endpoints = Endpoints {
+            twitch_helix: url,
+            ..Endpoints::default()
+        };
+        let task = RefreshTask {
+            dirs: vec![dir.clone()],
+            verbosity: -2,
+            twitch: Ok(Context::test(endpoints, &dir)),
+            youtube: Err(Error::Token("none".to_string())),
+        };
+        assert_eq!(task.run(), report::SUCCESS);
+        let paths: Vec<String> = server
+            .join()
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "/videos?id=1&id=2&id=3",
+                "/videos?id=1",
+                "/videos?id=2",
+                "/videos?id=3"
+            ]
+        );
+
+        let revisions = history(&dir, "1");
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0]["metadata"]["title"], "Video 1");
+        let (_, video) = archived(&dir.join("1.json")).unwrap().unwrap();
+        assert_eq!(video.metadata["title"], "Renamed");
+        assert_eq!(video.metadata["extra"]["view_count"], 100);
+
+        let revisions = history(&dir, "2");
+        assert_eq!(revisions.len(), 1);
+        assert!(!revisions[0]["metadata"]
+            .as_object()
+            .unwrap()
+            .contains_key("unavailable_since"));
+        let (_, video) = archived(&dir.join("2.json")).unwrap().unwrap();
+        assert!(video.metadata.contains_key("unavailable_since"));
+
+        assert!(history(&dir, "3").is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn changed_compares_extra_by_key_without_volatile_keys() {
+        let metadata = |extra: Value| {
+            let Value::Object(map) = json!({ "title": "A", "extra": extra }) else {
+                unreachable!()
+            };
+            map
+        };
+        let old =
+            metadata(json!({ "etag": "1", "statistics": { "viewCount": "1" }, "tags": ["a"] }));
+        let new = metadata(
+            json!({ "etag": "2", "statistics": { "viewCount": "2" }, "tags": ["b"], "new": 1 }),
+        );
+        assert_eq!(changed(&old, &new), ["extra.tags", "extra.new"]);
+        assert!(changed(
+            &old,
+            &metadata(json!({ "etag": "2", "statistics": {}, "tags": ["a"] }))
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use super::chapters;
+use super::muted;
+use super::retry;
+use super::utils::{command, finish_part, loggers, part_path, remove_part, verified, VideoInfo};
+use crate::init::external::External;
+use crate::init::Context;
+use crate::Error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Remux {
+    /// Embed the metadata, thumbnail and chapters into every downloaded video
+    pub(crate) enabled: bool,
+}
+
+/// Remuxes `video` without re-encoding it, embedding the tags of `info`,
+/// `{id}.jpg` as cover art and the chapters of `{id}.json`, marking the muted
+/// segments when they are embedded. The embedded metadata is kept in `{id}.ffmetadata`.
+pub(super) fn remux<T: VideoInfo>(
+    info: &T,
+    dir: &Path,
+    video: &Path,
+    context: &Context,
+) -> Result<(), Error> {
+    let id = info.id();
+    let path = dir.join(format!("{id}.ffmetadata"));
+    if verified(&path) {
+        return Err(Error::AlreadyExists);
+    }
+    if !verified(video) {
+        return Err(Error::Expected);
+    }
+    if context.missing.contains(&External::Ffmpeg) {
+        return Err(Error::MissingProgram(External::Ffmpeg));
+    }
+    let metadata = info.metadata();
+    let muted = match context.muted.embed {
+        true => muted::ranges(&info.to_video().muted_segments.unwrap_or_default()),
+        false => Vec::new(),
+    };
+    let timeline = chapters::timeline(
+        &chapters::read(&dir.join(format!("{id}.json")))?,
+        &muted,
+        metadata.duration,
+    );
+    let ffmetadata = chapters::ffmetadata(
+        &[
+            ("title", &metadata.title),
+            ("artist", &metadata.channel_name),
+            ("date", &metadata.created_at),
+            (
+                "description",
+                metadata.description.as_deref().unwrap_or_default(),
+            ),
+            ("comment", &metadata.url),
+        ],
+        &timeline,
+    )?;
+    remove_part(&path)?;
+    let metadata_part = part_path(&path);
+    fs::write(&metadata_part, ffmetadata).map_err(|err| Error::io(err, &metadata_part))?;
+
+    remove_part(video)?;
+    let thumbnail = dir.join(format!("{id}.jpg"));
+    let mut ffmpeg = command(&External::Ffmpeg, Path::new(""));
+    ffmpeg
+        .args(["-v", "error", "-y", "-i"])
+        .arg(video)
+        .args(["-f", "ffmetadata", "-i"])
+        .arg(&metadata_part);
+    match verified(&thumbnail) {
+        // `0:V` leaves out the cover of a video that was remuxed before
+        true => ffmpeg.arg("-i").arg(&thumbnail).args([
+            "-map",
+            "0:V",
+            "-map",
+            "0:a?",
+            "-map",
+            "2",
+            "-disposition:v:1",
+            "attached_pic",
+        ]),
+        false => ffmpeg.args(["-map", "0"]),
+    };
+    let (log, err_log) = loggers(dir.join(format!("{id}.remux")), context.logging);
+    retry::status(
+        External::Ffmpeg,
+        ffmpeg
+            .args(["-map_metadata", "1", "-map_chapters", "1", "-c", "copy"])
+            .arg(part_path(video))
+            .stdout(log)
+            .stderr(err_log),
+        context,
+    )?;
+    finish_part(video)?;
+    finish_part(&path)
+}
@@ -0,0 +1,394 @@
+use super::index::Stage;
+use super::muted;
+use super::twitch::MutedSegments;
+use crate::Error;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+pub(crate) const SUCCESS: u8 = 0;
+pub(crate) const PARTIAL_FAILURE: u8 = 1;
+pub(crate) const TOTAL_FAILURE: u8 = 3;
+pub(crate) const CONFIG_ERROR: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Status {
+    Succeeded,
+    Exists,
+    Archived,
+    Failed,
+}
+
+/// Why a requested video could not be archived at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Reason {
+    /// Not a valid id or URL for the platform
+    Invalid,
+    Deleted,
+    /// Exists but is not public, e.g. private or unpublished
+    Private,
+    /// Allowed in no region, videos blocked only in some regions are still tried
+    RegionBlocked,
+    /// Still live, upcoming or processing
+    Processing,
+    /// Left out by the API without a known reason
+    Unavailable,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MissingReport {
+    pub(crate) id: String,
+    pub(crate) reason: Reason,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct StageReport {
+    pub(crate) status: Status,
+    pub(crate) path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct VideoReport {
+    pub(crate) id: String,
+    pub(crate) title: String,
+    pub(crate) stages: BTreeMap<Stage, StageReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) muted: Vec<MutedSegments>,
+    /// Whether the muted segments differ from the ones seen by an earlier run
+    #[serde(skip_serializing_if = "is_false")]
+    pub(crate) muted_changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Report {
+    pub(crate) exit_code: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub(crate) youtube_quota_units: u64,
+    pub(crate) videos: Vec<VideoReport>,
+    /// Requested videos that were not found upstream
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) missing: Vec<MissingReport>,
+}
+
+impl Report {
+    pub(crate) fn start(&mut self, id: &str, title: &str) {
+        if self.video(id).is_none() {
+            self.videos.push(VideoReport {
+                id: id.to_string(),
+                title: title.to_string(),
+                stages: BTreeMap::new(),
+                muted: Vec::new(),
+                muted_changed: false,
+                error: None,
+            });
+        }
+    }
+
+    pub(crate) fn missing(&mut self, id: &str, reason: Reason) {
+        if !self.missing.iter().any(|missing| missing.id == id) {
+            self.missing.push(MissingReport {
+                id: id.to_string(),
+                reason,
+            });
+        }
+    }
+
+    /// Moves the outcome of `id` into a report of its own.
+    pub(crate) fn take(&mut self, id: &str) -> Report {
+        let (videos, rest) = std::mem::take(&mut self.videos)
+            .into_iter()
+            .partition(|video| video.id == id);
+        self.videos = rest;
+        Report {
+            videos,
+            ..Report::default()
+        }
+    }
+
+    /// Records the muted segments of `id`, adding the video when they changed
+    /// even though it is not being archived, as when watching. A change stays
+    /// marked when the segments are checked again later in the run.
+    pub(crate) fn muted(
+        &mut self,
+        id: &str,
+        title: &str,
+        segments: &[MutedSegments],
+        changed: bool,
+    ) {
+        if changed {
+            self.start(id, title);
+        }
+        if let Some(video) = self.video(id) {
+            video.muted = segments.to_vec();
+            video.muted_changed |= changed;
+        }
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        id: &str,
+        stage: Stage,
+        path: &Path,
+        result: &Result<(), Error>,
+    ) {
+        let (status, error) = match result {
+            Ok(()) => (Status::Succeeded, None),
+            Err(Error::AlreadyExists | Error::ProcessedChatAlreadyExists) => (Status::Exists, None),
+            Err(Error::AlreadyArchived) => (Status::Archived, None),
+            Err(Error::Expected) => return,
+            Err(Error::Stage { source, .. }) => (Status::Failed, Some(source.describe())),
+            Err(error) => (Status::Failed, Some(error.describe())),
+        };
+        self.start(id, "");
+        if let Some(video) = self.video(id) {
+            // A stage that ran earlier in the same run keeps its outcome
+            if matches!(status, Status::Exists | Status::Archived)
+                && video
+                    .stages
+                    .get(&stage)
+                    .is_some_and(|stage| matches!(stage.status, Status::Succeeded | Status::Failed))
+            {
+                return;
+            }
+            video.stages.insert(
+                stage,
+                StageReport {
+                    status,
+                    path: path.to_string_lossy().into_owned(),
+                    error,
+                },
+            );
+        }
+    }
+
+    pub(crate) fn fail(&mut self, id: Option<&str>, error: &Error) {
+        match id {
+            Some(id) => {
+                self.start(id, "");
+                if let Some(video) = self.video(id) {
+                    video.error = Some(error.describe());
+                }
+            }
+            None => self.error = Some(error.describe()),
+        }
+    }
+
+    pub(crate) fn is_failed(&self, id: &str) -> bool {
+        self.videos
+            .iter()
+            .any(|video| video.id == id && video.failed())
+    }
+
+    pub(crate) fn failed(&self) -> usize {
+        self.videos.iter().filter(|video| video.failed()).count()
+    }
+
+    /// Sets the exit code, counting missing videos as failed when `fail_on_missing`.
+    pub(crate) fn finish(&mut self, fail_on_missing: bool) -> u8 {
+        let (mut failed, mut total) = (self.failed(), self.videos.len());
+        if fail_on_missing {
+            failed += self.missing.len();
+            total += self.missing.len();
+        }
+        self.exit_code = if self.error.is_some() || (failed > 0 && failed == total) {
+            TOTAL_FAILURE
+        } else if failed > 0 {
+            PARTIAL_FAILURE
+        } else {
+            SUCCESS
+        };
+        self.exit_code
+    }
+
+    pub(crate) fn table(&self) -> String {
+        const STAGES: [Stage; 8] = [
+            Stage::Json,
+            Stage::Thumbnail,
+            Stage::Chat,
+            Stage::Emotes,
+            Stage::Html,
+            Stage::ChatProcess,
+            Stage::Video,
+            Stage::Remux,
+        ];
+        let id_width = self
+            .videos
+            .iter()
+            .map(|video| video.id.len())
+            .chain([2])
+            .max()
+            .unwrap_or(2);
+        let mut table = format!("{:id_width$}", "ID").bold().to_string();
+        for stage in STAGES {
+            table.push_str(&format!("  {}", format!("{:12}", stage.to_string()).bold()));
+        }
+        for video in &self.videos {
+            table.push_str(&format!("\n{:id_width$}", video.id));
+            for stage in STAGES {
+                let cell = match video.stages.get(&stage).map(|stage| stage.status) {
+                    Some(Status::Succeeded) => format!("{:12}", "ok").bright_green(),
+                    Some(Status::Exists) => format!("{:12}", "exists").bright_yellow(),
+                    Some(Status::Archived) => format!("{:12}", "archived").bright_yellow(),
+                    Some(Status::Failed) => format!("{:12}", "failed").bright_red(),
+                    None => format!("{:12}", "-").normal(),
+                };
+                table.push_str(&format!("  {cell}"));
+            }
+        }
+        for video in self
+            .videos
+            .iter()
+            .filter(|video| !video.muted.is_empty() || video.muted_changed)
+        {
+            let line = match video.muted.is_empty() {
+                true => format!("\n{} has no muted audio", video.id),
+                false => format!(
+                    "\n{} has muted audio at {}",
+                    video.id,
+                    muted::describe(&video.muted)
+                ),
+            };
+            table.push_str(&match video.muted_changed {
+                true => format!("{line}, changed since archiving")
+                    .bright_yellow()
+                    .to_string(),
+                false => line,
+            });
+        }
+        for missing in &self.missing {
+            table.push_str(&format!(
+                "\n{}",
+                format!("{} was not archived, it is {}", missing.id, missing.reason)
+                    .bright_yellow()
+            ));
+        }
+        let failed = self.failed();
+        table.push_str(&format!(
+            "\n{} videos, {} succeeded, {} failed",
+            self.videos.len(),
+            self.videos.len() - failed,
+            failed
+        ));
+        if !self.missing.is_empty() {
+            table.push_str(&format!(", {} missing", self.missing.len()));
+        }
+        if self.youtube_quota_units > 0 {
+            table.push_str(&format!(
+                ", an estimated {} YouTube API quota units used",
+                self.youtube_quota_units
+            ));
+        }
+        table
+    }
+
+    pub(crate) fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        fs::write(path, json).map_err(|err| Error::io(err, path))
+    }
+
+    fn video(&mut self, id: &str) -> Option<&mut VideoReport> {
+        self.videos.iter_mut().find(|video| video.id == id)
+    }
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Reason::Invalid => write!(f, "not a valid id or URL"),
+            Reason::Deleted => write!(f, "deleted or never existed"),
+            Reason::Private => write!(f, "private or unpublished"),
+            Reason::RegionBlocked => write!(f, "blocked in every region"),
+            Reason::Processing => write!(f, "still live, upcoming or processing"),
+            Reason::Unavailable => write!(f, "unavailable for an unknown reason"),
+        }
+    }
+}
+
+impl VideoReport {
+    fn failed(&self) -> bool {
+        self.error.is_some()
+            || self
+                .stages
+                .values()
+                .any(|stage| stage.status == Status::Failed)
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A report of videos whose video stage failed unless `true`, with the missing ids.
+    fn report(videos: &[bool], missing: &[&str], error: bool) -> Report {
+        let mut report = Report::default();
+        for (number, succeeded) in videos.iter().enumerate() {
+            let result = match succeeded {
+                true => Err(Error::AlreadyExists),
+                false => Err(Error::Token("expired".to_string())),
+            };
+            let id = number.to_string();
+            report.record(&id, Stage::Json, Path::new("1.json"), &Ok(()));
+            report.record(&id, Stage::Video, Path::new("1.mp4"), &result);
+        }
+        for id in missing {
+            report.missing(id, Reason::Deleted);
+        }
+        if error {
+            report.fail(None, &Error::Token("expired".to_string()));
+        }
+        report
+    }
+
+    #[test]
+    fn exit_codes() {
+        let cases = [
+            (report(&[], &[], false), false, SUCCESS),
+            (report(&[true, true], &[], false), false, SUCCESS),
+            (report(&[true, false], &[], false), false, PARTIAL_FAILURE),
+            (report(&[false, false], &[], false), false, TOTAL_FAILURE),
+            (report(&[true], &["9"], false), false, SUCCESS),
+            (report(&[true], &["9"], false), true, PARTIAL_FAILURE),
+            (report(&[], &["9"], false), false, SUCCESS),
+            (report(&[], &["9"], false), true, TOTAL_FAILURE),
+            (report(&[false], &["9"], false), true, TOTAL_FAILURE),
+            (report(&[], &[], true), false, TOTAL_FAILURE),
+            (report(&[true, true], &[], true), false, TOTAL_FAILURE),
+        ];
+        for (number, (mut report, fail_on_missing, expected)) in cases.into_iter().enumerate() {
+            assert_eq!(report.finish(fail_on_missing), expected, "case {number}");
+            assert_eq!(report.exit_code, expected, "case {number}");
+        }
+    }
+
+    #[test]
+    fn video_errors_count_as_failed() {
+        let mut report = report(&[true, true], &[], false);
+        report.fail(Some("1"), &Error::Token("expired".to_string()));
+        assert!(report.is_failed("1"));
+        assert!(!report.is_failed("0"));
+        assert_eq!(report.finish(false), PARTIAL_FAILURE);
+    }
+}
@@ -0,0 +1,178 @@
+use super::utils::message;
+use super::{Context, External};
+use crate::Error;
+use reqwest::blocking::{RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Retry {
+    /// Total number of tries, including the first one
+    pub(crate) attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled after every retry
+    pub(crate) backoff: u64,
+    /// Upper bound for the delay in milliseconds
+    pub(crate) max_backoff: u64,
+    /// Fraction of the delay to randomly add or subtract
+    pub(crate) jitter: f64,
+    /// HTTP statuses that are retried
+    pub(crate) statuses: Vec<u16>,
+    /// Exit codes of external programs that are retried, none by default since
+    /// yt-dlp also exits with `1` for private, removed, or geo-blocked videos
+    pub(crate) exit_codes: Vec<i32>,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: 2000,
+            max_backoff: 60000,
+            jitter: 0.2,
+            statuses: vec![408, 429, 500, 502, 503, 504],
+            exit_codes: Vec::new(),
+        }
+    }
+}
+
+impl Retry {
+    fn retryable(&self, error: &Error) -> bool {
+        match error {
+            Error::Request(error) => match error.status() {
+                Some(status) => self.statuses.contains(&status.as_u16()),
+                None => error.is_timeout() || error.is_connect() || error.is_body(),
+            },
+            Error::CommandFailed(_, Some(code)) => self.exit_codes.contains(code),
+            // Killed by a signal, most likely an interrupt from the user
+            Error::CommandFailed(_, None) => false,
+            _ => false,
+        }
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .backoff
+            .saturating_mul(2_u64.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_backoff);
+        let jitter = 1.0 + self.jitter.clamp(0.0, 1.0) * (fastrand::f64() * 2.0 - 1.0);
+        Duration::from_millis(backoff).mul_f64(jitter)
+    }
+}
+
+pub(crate) fn run<T>(
+    context: &Context,
+    mut attempt: impl FnMut() -> Result<T, Error>,
+) -> Result<T, Error> {
+    let policy = &context.retry;
+    let mut retry = 0;
+    loop {
+        match attempt() {
+            Err(error) if retry + 1 < policy.attempts && policy.retryable(&error) => {
+                retry += 1;
+                let delay = policy.delay(retry);
+                message(
+                    &format!(
+                        "[retry] {}, retrying in {:.1}s ({}/{})",
+                        error.describe(),
+                        delay.as_secs_f64(),
+                        retry,
+                        policy.attempts - 1
+                    ),
+                    context,
+                    1,
+                );
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+pub(crate) fn send(
+    context: &Context,
+    request: impl Fn() -> RequestBuilder,
+) -> Result<Response, Error> {
+    run(context, || {
+        context.rate_limit.wait(context);
+        let response = request().send()?;
+        context.rate_limit.update(&response);
+        if context.retry.statuses.contains(&response.status().as_u16()) {
+            response.error_for_status_ref()?;
+        }
+        Ok(response)
+    })
+}
+
+pub(crate) fn status(
+    program: External,
+    command: &mut Command,
+    context: &Context,
+) -> Result<(), Error> {
+    run(context, || {
+        let status = command.status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(Error::CommandFailed(program, status.code())),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retryable_errors() {
+        let retry = Retry {
+            exit_codes: vec![2],
+            ..Retry::default()
+        };
+        let connect = reqwest::blocking::get("http://127.0.0.1:1").unwrap_err();
+        let cases = [
+            (Error::CommandFailed(External::YtDlp, Some(2)), true),
+            (Error::CommandFailed(External::YtDlp, Some(1)), false),
+            (Error::CommandFailed(External::Ffmpeg, None), false),
+            (Error::Request(connect), true),
+            (Error::Token("expired".to_string()), false),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(retry.retryable(&error), expected, "{error}");
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_maximum() {
+        let retry = Retry {
+            backoff: 1000,
+            max_backoff: 5000,
+            jitter: 0.0,
+            ..Retry::default()
+        };
+        let delays: Vec<u128> = (1..=5).map(|n| retry.delay(n).as_millis()).collect();
+        assert_eq!(delays, [1000, 2000, 4000, 5000, 5000]);
+        assert_eq!(retry.delay(u32::MAX).as_millis(), 5000);
+    }
+
+    #[test]
+    fn delay_stays_within_the_jitter() {
+        let retry = Retry {
+            backoff: 1000,
+            jitter: 0.2,
+            ..Retry::default()
+        };
+        for _ in 0..100 {
+            let delay = retry.delay(1).as_millis();
+            assert!((800..=1200).contains(&delay), "{delay}");
+        }
+        let unbounded = Retry {
+            jitter: 5.0,
+            ..retry
+        };
+        for _ in 0..100 {
+            assert!(unbounded.delay(1).as_millis() <= 2000);
+        }
+    }
+}
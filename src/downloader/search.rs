@@ -0,0 +1,162 @@
+use super::chat::{self, timestamp, Chat, Line};
+use super::compress;
+use super::metadata::Metadata;
+use super::utils::error;
+use crate::init::SearchTask;
+use crate::report;
+use crate::Error;
+use chrono::{DateTime, NaiveDate};
+use colored::Colorize;
+use shell_escape::escape;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+impl SearchTask {
+    /// Prints the matching messages, or yt-dlp commands for their sections,
+    /// returning `PARTIAL_FAILURE` when nothing matched or a chat could not be read.
+    pub(crate) fn run(&self) -> u8 {
+        let mut files = Vec::new();
+        for dir in &self.dirs {
+            if let Err(err) = walk(dir, &mut files) {
+                error(&format!("Could not search: {}", err.describe()), None);
+            }
+        }
+        let (mut found, mut failed) = (0, 0);
+        for ((dir, name), file) in chats(files) {
+            let metadata = Metadata::read(&dir.join(format!("{name}.json")));
+            if !self.in_range(metadata.date.as_deref()) {
+                continue;
+            }
+            let chat = match Chat::read(&file) {
+                Ok(chat) => chat,
+                Err(err) => {
+                    error(
+                        &format!("Could not read {}: {}", file.display(), err.describe()),
+                        None,
+                    );
+                    failed += 1;
+                    continue;
+                }
+            };
+            let hits: Vec<&Line> = chat
+                .lines
+                .iter()
+                .filter(|line| self.matches(line))
+                .collect();
+            if hits.is_empty() {
+                continue;
+            }
+            found += hits.len();
+            let title = metadata.title.unwrap_or(chat.title);
+            match self.sections {
+                true => println!(
+                    "{}",
+                    self.download_sections(&hits, metadata.url.as_deref().unwrap_or(&name))
+                ),
+                false => {
+                    for line in hits {
+                        println!(
+                            "{} @ {} {}: {}",
+                            title.bold(),
+                            timestamp(line.offset, "", 0, false),
+                            line.name.bright_blue(),
+                            line.text.replace(['\n', '\r'], " ")
+                        );
+                    }
+                }
+            }
+        }
+        match (found, failed) {
+            (0, _) | (_, 1..) => report::PARTIAL_FAILURE,
+            _ => report::SUCCESS,
+        }
+    }
+
+    fn matches(&self, line: &Line) -> bool {
+        (self.users.is_empty() || self.users.contains(&line.name.to_lowercase()))
+            && self.pattern.is_match(&line.text).unwrap_or_default()
+    }
+
+    /// Whether a video created at `date` is within `after` and `before`,
+    /// videos without a date only match when neither is set.
+    fn in_range(&self, date: Option<&str>) -> bool {
+        if self.after.is_none() && self.before.is_none() {
+            return true;
+        }
+        let Some(date) = date.and_then(|date| DateTime::parse_from_rfc3339(date).ok()) else {
+            return false;
+        };
+        let date: NaiveDate = date.date_naive();
+        self.after.is_none_or(|after| date >= after)
+            && self.before.is_none_or(|before| date <= before)
+    }
+
+    /// A yt-dlp command downloading `padding` seconds around every hit,
+    /// merging the sections that overlap.
+    fn download_sections(&self, hits: &[&Line], url: &str) -> String {
+        let padding = f64::from(self.padding);
+        let mut sections: Vec<(f64, f64)> = Vec::new();
+        for line in hits {
+            let (start, end) = ((line.offset - padding).max(0.0), line.offset + padding);
+            match sections.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => sections.push((start, end)),
+            }
+        }
+        let mut command = "yt-dlp".to_string();
+        for (start, end) in sections {
+            command.push_str(&format!(
+                " --download-sections \"*{}-{}\"",
+                timestamp(start, "", 0, false),
+                timestamp(end, "", 0, false)
+            ));
+        }
+        command.push_str(&format!(" {}", escape(url.into())));
+        command
+    }
+}
+
+/// Collects every file under `dir`, in order.
+pub(super) fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(|err| Error::io(err, dir))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(|err| Error::io(err, dir))?;
+    entries.sort();
+    for path in entries {
+        match path.is_dir() {
+            true => walk(&path, files)?,
+            false => files.push(path),
+        }
+    }
+    Ok(())
+}
+
+/// The chat of every video in `files` by its directory and name, preferring
+/// JSON over SSA and uncompressed files over compressed ones.
+fn chats(files: Vec<PathBuf>) -> BTreeMap<(PathBuf, String), PathBuf> {
+    let mut chats: BTreeMap<(PathBuf, String), (u8, PathBuf)> = BTreeMap::new();
+    for file in files {
+        let stripped = compress::strip(&file);
+        let name = stripped.file_name().unwrap_or_default().to_string_lossy();
+        let kind = if name.ends_with(".chat.json") {
+            0
+        } else if name.ends_with(".ssa") {
+            2
+        } else {
+            continue;
+        };
+        let rank = kind + u8::from(stripped != file);
+        let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+        let key = (dir, chat::name(&file));
+        if chats.get(&key).is_none_or(|(best, _)| rank < *best) {
+            chats.insert(key, (rank, file));
+        }
+    }
+    chats
+        .into_iter()
+        .map(|(key, (_, file))| (key, file))
+        .collect()
+}
@@ -0,0 +1,246 @@
+use super::chapters::{self, Chapter};
+use super::chat::{timestamp, Chat};
+use super::emotes::Manifest;
+use super::utils::write_file;
+use crate::Error;
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Most rows the messages per minute histogram is printed with.
+const HISTOGRAM_ROWS: usize = 40;
+const BAR_WIDTH: u64 = 40;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Stats {
+    pub(crate) title: String,
+    pub(crate) messages: usize,
+    pub(crate) unique_chatters: usize,
+    /// Messages sent during each minute of the video
+    pub(crate) messages_per_minute: Vec<u64>,
+    pub(crate) top_chatters: Vec<Count>,
+    pub(crate) top_emotes: Vec<Count>,
+    pub(crate) spikes: Vec<Spike>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Count {
+    pub(crate) name: String,
+    pub(crate) count: u64,
+}
+
+/// Consecutive minutes with far more messages than usual.
+#[derive(Debug, Serialize)]
+pub(crate) struct Spike {
+    /// Seconds from the start of the video
+    pub(crate) start: f64,
+    pub(crate) end: f64,
+    pub(crate) timestamp: String,
+    pub(crate) messages: u64,
+    /// Messages sent during the busiest minute
+    pub(crate) peak: u64,
+}
+
+impl Stats {
+    /// Counts the messages of `chat`, recognizing the emotes marked by the
+    /// platform and the ones in `manifest`. A minute is part of a spike when it
+    /// has more than `threshold` standard deviations above the average messages.
+    pub(crate) fn new(chat: &Chat, manifest: &Manifest, top: usize, threshold: f64) -> Self {
+        let mut messages_per_minute: Vec<u64> = Vec::new();
+        let mut chatters: HashMap<String, (String, u64)> = HashMap::new();
+        let mut emotes: HashMap<&str, u64> = HashMap::new();
+        for line in &chat.lines {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let minute = (line.offset.max(0.0) / 60.0) as usize;
+            if messages_per_minute.len() <= minute {
+                messages_per_minute.resize(minute + 1, 0);
+            }
+            messages_per_minute[minute] += 1;
+            chatters
+                .entry(line.name.to_lowercase())
+                .or_insert_with(|| (line.name.clone(), 0))
+                .1 += 1;
+            let native: HashSet<&str> = line.emotes.iter().map(String::as_str).collect();
+            for word in line.text.split_whitespace() {
+                if native.contains(word) || manifest.emotes.contains_key(word) {
+                    *emotes.entry(word).or_default() += 1;
+                }
+            }
+        }
+        let spikes = spikes(&messages_per_minute, threshold);
+        Self {
+            title: chat.title.clone(),
+            messages: chat.lines.len(),
+            unique_chatters: chatters.len(),
+            messages_per_minute,
+            top_chatters: ranked(chatters.into_values(), top),
+            top_emotes: ranked(
+                emotes
+                    .into_iter()
+                    .map(|(name, count)| (name.to_string(), count)),
+                top,
+            ),
+            spikes,
+        }
+    }
+
+    pub(crate) fn table(&self) -> String {
+        let mut table = format!("{}\n", self.title.bold());
+        let minutes = self.messages_per_minute.len();
+        #[allow(clippy::cast_precision_loss)]
+        let average = self.messages as f64 / minutes.max(1) as f64;
+        for (label, value) in [
+            ("Messages", self.messages.to_string()),
+            ("Chatters", self.unique_chatters.to_string()),
+            ("Duration", timestamp(minutes as f64 * 60.0, "", 0, false)),
+            ("Average", format!("{average:.1} messages per minute")),
+        ] {
+            table.push_str(&format!("{}{value}\n", format!("{label:10}").bold()));
+        }
+
+        let bucket = minutes.div_ceil(HISTOGRAM_ROWS).max(1);
+        let rows: Vec<u64> = self
+            .messages_per_minute
+            .chunks(bucket)
+            .map(|chunk| chunk.iter().sum::<u64>() / chunk.len() as u64)
+            .collect();
+        let max = rows.iter().copied().max().unwrap_or_default().max(1);
+        table.push_str(&format!("\n{}\n", "Messages per minute".bold()));
+        for (row, count) in rows.iter().enumerate() {
+            let start = row * bucket * 60;
+            let bar = "█".repeat(usize::try_from(count * BAR_WIDTH / max).unwrap_or_default());
+            let spiking = self.spikes.iter().any(|spike| {
+                spike.start < (start + bucket * 60) as f64 && spike.end > start as f64
+            });
+            let bar = match spiking {
+                true => bar.bright_red(),
+                false => bar.normal(),
+            };
+            table.push_str(&format!(
+                "{:>8}  {bar} {count}\n",
+                timestamp(start as f64, "", 0, false)
+            ));
+        }
+
+        for (heading, counts) in [
+            ("Top chatters", &self.top_chatters),
+            ("Top emotes", &self.top_emotes),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+            table.push_str(&format!("\n{}\n", heading.bold()));
+            let width = counts.iter().map(|count| count.name.chars().count()).max();
+            for count in counts {
+                table.push_str(&format!(
+                    "{:width$}  {}\n",
+                    count.name,
+                    count.count,
+                    width = width.unwrap_or_default()
+                ));
+            }
+        }
+
+        table.push_str(&format!("\n{}\n", "Spikes".bold()));
+        if self.spikes.is_empty() {
+            table.push_str("None\n");
+        }
+        for spike in &self.spikes {
+            table.push_str(&format!(
+                "{:>8}  {} messages, {} in the busiest minute\n",
+                spike.timestamp, spike.messages, spike.peak
+            ));
+        }
+        table.pop();
+        table
+    }
+}
+
+/// Writes `{name}.stats.json` for a chat file into `output_dir`, reading
+/// `{name}.emotes.json` from beside it, and adds the spikes as chapters to
+/// `{name}.json` if `chapters` is set.
+pub(super) fn stats_file(
+    file: &Path,
+    name: &str,
+    output_dir: &Path,
+    top: usize,
+    threshold: f64,
+    chapters: bool,
+) -> Result<(Stats, PathBuf), Error> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let chat = Chat::read(file)?;
+    let manifest = Manifest::read(&dir.join(format!("{name}.emotes.json")))?;
+    let stats = Stats::new(&chat, &manifest, top, threshold);
+    let path = output_dir.join(format!("{name}.stats.json"));
+    let mut json = serde_json::to_string_pretty(&stats)?;
+    json.push('\n');
+    write_file(&path, json.as_bytes())?;
+    if chapters {
+        let spikes = stats
+            .spikes
+            .iter()
+            .map(|spike| Chapter {
+                start: spike.start,
+                end: Some(spike.end),
+                title: format!("Chat spike, {} messages", spike.messages),
+                source: "chat".to_string(),
+            })
+            .collect();
+        chapters::merge(&dir.join(format!("{name}.json")), "chat", spikes)?;
+    }
+    Ok((stats, path))
+}
+
+/// Groups consecutive minutes above the threshold into spikes.
+fn spikes(messages_per_minute: &[u64], threshold: f64) -> Vec<Spike> {
+    #[allow(clippy::cast_precision_loss)]
+    let counts: Vec<f64> = messages_per_minute
+        .iter()
+        .map(|&count| count as f64)
+        .collect();
+    #[allow(clippy::cast_precision_loss)]
+    let minutes = counts.len().max(1) as f64;
+    let mean = counts.iter().sum::<f64>() / minutes;
+    let deviation = (counts
+        .iter()
+        .map(|count| (count - mean).powi(2))
+        .sum::<f64>()
+        / minutes)
+        .sqrt();
+    let cutoff = mean + threshold * deviation;
+    let mut spikes: Vec<Spike> = Vec::new();
+    let mut previous = None;
+    for (minute, &count) in messages_per_minute.iter().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        if deviation == 0.0 || (count as f64) <= cutoff {
+            continue;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let start = (minute * 60) as f64;
+        match spikes.last_mut() {
+            Some(spike) if previous.is_some_and(|previous| previous + 1 == minute) => {
+                spike.end = start + 60.0;
+                spike.messages += count;
+                spike.peak = spike.peak.max(count);
+            }
+            _ => spikes.push(Spike {
+                start,
+                end: start + 60.0,
+                timestamp: timestamp(start, "", 0, false),
+                messages: count,
+                peak: count,
+            }),
+        }
+        previous = Some(minute);
+    }
+    spikes
+}
+
+/// The `top` highest counts, ties broken by name.
+fn ranked(counts: impl Iterator<Item = (String, u64)>, top: usize) -> Vec<Count> {
+    let mut counts: Vec<Count> = counts.map(|(name, count)| Count { name, count }).collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    counts.truncate(top);
+    counts
+}
@@ -1,17 +1,23 @@
+use super::chapters::{self, Chapter};
 use super::common::{self, filename};
-use super::utils::{colorize, error, loggers, message, VideoInfo};
+use super::metadata::{VideoMetadata, SCHEMA_VERSION};
+use super::report::Reason;
+use super::utils::{
+    api_message, colorize, command, error, from_raw, loggers, message, remove_part, verified,
+    VideoInfo,
+};
 use super::youtube::YtVideo;
-use super::Context;
+use super::{chat, gql, retry, Context};
 use crate::init::external::External;
 use crate::Error;
 use colored::Color;
 use derive_more::Constructor;
 use fancy_regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::cmp::min;
+use serde_json::Value;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::path::Path;
-use std::process::Command;
+use std::slice;
 use std::sync::LazyLock;
 
 static RE: LazyLock<Regex> =
@@ -20,56 +26,100 @@ static CHANNEL: LazyLock<Regex> = LazyLock::new(|| unsafe {
     Regex::new(r"^(?:https?://)?(?:www.)?(?:twitch.tv/)?(?:[^/]+/)?([^?/\& *+]+)")
         .unwrap_unchecked()
 });
+/// GQL statuses of videos that are still being recorded or processed.
+const PROCESSING_STATUSES: [&str; 6] = [
+    "CREATED",
+    "RECORDING",
+    "UPLOADING",
+    "PENDING_TRANSCODE",
+    "TRANSCODING",
+    "UNPROCESSED",
+];
+/// Most ids Helix takes in one request.
+const BATCH: usize = 100;
 const PROCESSING: &str = "https://vod-secure.twitch.tv/_404/404_processing_%{width}x%{height}.png";
 
 pub(super) fn id2info<T: VideoInfo>(
-    ids: &[String],
+    requested: &[String],
     r#type: &str,
-    context: &mut Context,
+    context: &Context,
+    missing: &mut Vec<(String, Reason)>,
 ) -> Result<Vec<T>, Error> {
-    let max = 100;
-    message(&format!("[id2info] Getting info for {ids:?}"), context, 3);
-    let mut info = Vec::new();
-    let mut ids: Vec<String> = ids.to_vec();
-    while !ids.is_empty() {
-        let mut query = String::new();
-        let mut iter = ids.drain(0..min(ids.len(), max));
-        query.push_str(format!("?id={}", &iter.next().unwrap()).as_str());
-        for id in iter {
-            query.push_str(format!("&id={id}").as_str());
-        }
-        message(&format!("[id2info] Query: {query}"), context, 3);
-        let endpoint = match r#type {
-            "highlight" | "archive" => "videos",
-            "clip" => "clips",
-            _ => return Err(Error::NoType),
+    message(
+        &format!("[id2info] Getting info for {requested:?}"),
+        context,
+        3,
+    );
+    let endpoint = match r#type {
+        "highlight" | "archive" => "videos",
+        "clip" => "clips",
+        _ => return Err(Error::NoType),
+    };
+    let ids: Vec<&str> = requested.iter().map(String::as_str).collect();
+    let mut info: Vec<T> = Vec::new();
+    for raw in lookup(endpoint, &ids, context)? {
+        let Ok(video) = from_raw::<T>(raw) else {
+            message(
+                &colorize(Some("id2info"), "Could not deserialize.", Color::BrightRed),
+                context,
+                -1,
+            );
+            continue;
         };
-        let url = format!("https://api.twitch.tv/helix/{endpoint}/{query}");
-        message(&format!("[id2info] URL: {url}"), context, 3);
-        if let Ok(response) = get(&url, context) {
-            message(&format!("[id2info] Response: {response}"), context, 3);
-            if let Ok(mut data) = serde_json::from_str::<TwitchResponse<T>>(&response) {
-                info.append(&mut data.data);
-            } else {
-                message(
-                    &colorize(Some("id2info"), "Could not deserialize.", Color::BrightRed),
-                    context,
-                    -1,
-                );
-            }
+        // Requested explicitly, so it is archived as far as it has been processed
+        if video.thumbnail_url() == PROCESSING {
+            message(
+                &colorize(
+                    Some("id2info"),
+                    &format!("{} is still being processed by Twitch", video.id()),
+                    Color::BrightYellow,
+                ),
+                context,
+                0,
+            );
         }
+        info.push(video);
     }
     message("[id2info] Info found:", context, 3);
     for inf in &info {
         message(&format!("  {inf}"), context, 3);
     }
+    for id in requested {
+        if info.iter().any(|info| info.id() == id) || missing.iter().any(|(m, _)| m == id) {
+            continue;
+        }
+        missing.push((id.clone(), missing_reason(id, r#type, context)));
+    }
     Ok(info)
 }
 
+/// Why Helix did not return a video, asking GQL whether it still exists.
+fn missing_reason(id: &str, r#type: &str, context: &Context) -> Reason {
+    if r#type == "clip" {
+        return Reason::Deleted;
+    }
+    match gql::status(id, context) {
+        Ok(None) => Reason::Deleted,
+        Ok(Some(status)) if PROCESSING_STATUSES.contains(&status.as_str()) => Reason::Processing,
+        Ok(Some(_)) => Reason::Private,
+        Err(err) => {
+            message(
+                &format!(
+                    "[id2info] Could not get the status of {id}: {}",
+                    err.describe()
+                ),
+                context,
+                2,
+            );
+            Reason::Unavailable
+        }
+    }
+}
+
 pub(super) fn get_channel_ids<T: VideoInfo>(
     channel: &str,
     r#type: &str,
-    context: &mut Context,
+    context: &Context,
 ) -> Result<Vec<T>, Error> {
     let ChannelData { username, id } = get_channel(channel, context)?;
     message(
@@ -85,7 +135,8 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
     let mut after = String::new();
     loop {
         let url = format!(
-            "https://api.twitch.tv/helix/videos?user_id={id}&type={type}&first=100&after={after}"
+            "{}/videos?user_id={id}&type={type}&first=100&after={after}",
+            context.endpoints.twitch_helix
         );
         message(&format!("[get_channel_ids] URL: {url}"), context, 3);
         let response = get(&url, context)?;
@@ -94,18 +145,23 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
             context,
             3,
         );
-        let data: PagedTwitchResponse<T> = match serde_json::from_str(&response) {
+        let data: PagedTwitchResponse<Value> = match serde_json::from_str(&response) {
             Ok(data) => data,
             Err(err) => {
                 message(&format!("[get_channel_ids] JSON Error: {err}"), context, 3);
                 return Err(err.into());
             }
         };
+        let mut page = Vec::new();
         for video in data.data {
+            let video: T = from_raw(video)?;
             if video.thumbnail_url() != PROCESSING {
-                videos.push(video);
+                page.push(video);
             }
         }
+        if !context.filter.extend(&mut videos, page, context) {
+            break;
+        }
         match data.pagination.cursor {
             Some(cursor) if cursor != after || after.is_empty() => after = cursor,
             _ => break,
@@ -114,115 +170,104 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
     Ok(videos)
 }
 
-pub(super) fn get_chat(id: &str, context: &mut Context) -> Result<(), Error> {
-    let chat_string = format!("{id}.ssa");
-    let chat = Path::new(&chat_string);
-    let json_string = format!("{id}.chat.json");
-    let json = Path::new(&json_string);
-    if chat.exists() && json.exists() {
-        return Err(Error::AlreadyExists);
-    }
-
-    let mut ssa_status = true;
-    let mut json_status = true;
-    let missing_tcd = context.missing.contains(&External::Tcd);
-    let missing_tdcli = context.missing.contains(&External::TdCli);
-
-    if !missing_tcd && !chat.exists() {
-        let (log, err_log) = loggers(&format!("{id}.chat"), context.logging);
-        ssa_status = Command::new(External::Tcd.command())
-            .args([
-                "-f",
-                "ssa",
-                "-v",
-                id,
-                "--filename-format",
-                "./{video_id}.{format}",
-            ])
-            .stdout(log)
-            .stderr(err_log)
-            .status()?
-            .success();
-    }
-
-    if !missing_tdcli && !json.exists() {
-        let (log, err_log) = loggers(&format!("{id}.json.chat"), context.logging);
-        json_status = Command::new(External::TdCli.command())
-            .args(["chatdownload", "-u", id, "-o", &format!("{id}.chat.json")])
-            .stdout(log)
-            .stderr(err_log)
-            .status()?
-            .success();
+pub(super) fn get_live_ids<T: VideoInfo>(
+    channel: &str,
+    context: &Context,
+) -> Result<Vec<T>, Error> {
+    let ChannelData { username, id } = get_channel(channel, context)?;
+    let url = format!(
+        "{}/streams?user_id={id}&type=live",
+        context.endpoints.twitch_helix
+    );
+    message(&format!("[get_live_ids] URL: {url}"), context, 3);
+    let response = get(&url, context)?;
+    message(&format!("[get_live_ids] Response: {response}"), context, 3);
+    let data: TwitchResponse<Value> = serde_json::from_str(&response)?;
+    if data.data.is_empty() {
+        message(
+            &format!("[get_live_ids] {username} is not live"),
+            context,
+            1,
+        );
     }
+    data.data
+        .into_iter()
+        .map(|raw| {
+            let stream = Stream::deserialize(&raw)?;
+            let mut info: T = serde_json::from_value(serde_json::to_value(Video::from(stream))?)?;
+            info.set_raw(raw);
+            Ok(info)
+        })
+        .collect()
+}
 
-    if missing_tcd {
-        return Err(Error::MissingProgram(External::Tcd));
-    }
-    if !ssa_status {
-        return Err(Error::CommandFailed(External::Tcd));
-    }
-    if missing_tdcli {
-        return Err(Error::MissingProgram(External::TdCli));
-    }
-    if !json_status {
-        return Err(Error::CommandFailed(External::TdCli));
+/// Saves `{id}.json` with the game changes fetched from GQL in `moments`,
+/// also added to its `chapters` from the `twitch` source.
+pub(super) fn save_json<T: VideoInfo>(
+    info: &T,
+    dir: &Path,
+    context: &Context,
+) -> Result<(), Error> {
+    let id = info.id();
+    let path = dir.join(format!("{id}.json"));
+    if verified(&path) {
+        return Err(Error::AlreadyExists);
     }
-    Ok(())
+    let mut metadata = info.metadata();
+    // Failing the stage has the game changes fetched again by the next run
+    let moments = gql::moments(id, context)?;
+    metadata.chapters = moments
+        .iter()
+        .map(|moment| Chapter {
+            start: moment.start,
+            end: Some(moment.start + moment.duration),
+            title: moment.game.clone(),
+            source: "twitch".to_string(),
+        })
+        .collect();
+    metadata.moments = Some(moments);
+    metadata.write(&path)
 }
 
-pub(super) fn process_chat(id: &str, context: &mut Context) -> Result<(), Error> {
-    let chat_string = format!("{id}.ssa");
-    let chat = Path::new(&chat_string);
-    let compressed_string = format!("{id}.ssa.br");
-    let compressed = Path::new(&compressed_string);
-    if compressed.exists() {
-        return Err(Error::ProcessedChatAlreadyExists);
-    }
-    if context.missing.contains(&External::Brotli) {
-        return Err(Error::MissingProgram(External::Brotli));
-    }
-    if !chat.exists() {
-        return Err(Error::NoChatFound);
-    }
-    let (log, err_log) = loggers(&format!("{id}.process_chat"), context.logging);
-    let status = Command::new(External::Brotli.command())
-        .args(["-q", "11", format!("{id}.ssa").as_str()])
-        .stdout(log)
-        .stderr(err_log)
-        .status()?;
-    if !status.success() {
-        return Err(Error::CommandFailed(External::Brotli));
+pub(super) fn get_chat(id: &str, dir: &Path, context: &Context) -> Result<(), Error> {
+    let json = &dir.join(format!("{id}.chat.json"));
+    if context.compression.exists(json) {
+        return Err(Error::AlreadyExists);
     }
-    Ok(())
+    remove_part(json)?;
+    chat::write(json, &gql::chat(id, context)?)
 }
 
-pub(super) fn get_video<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
-    let video_filename = filename(info.title().to_string(), info.id());
-    let video = Path::new(&video_filename);
+pub(super) fn get_video<T: VideoInfo>(
+    info: &T,
+    dir: &Path,
+    context: &Context,
+) -> Result<(), Error> {
+    let video = &dir.join(filename(info.title().to_string(), info.id()));
     let url = format!("https://www.twitch.tv/videos/{}", info.id());
-    if video.exists() {
+    if verified(video) {
         return Err(Error::AlreadyExists);
     }
     if context.missing.contains(&External::YtDlp) {
         return Err(Error::MissingProgram(External::YtDlp));
     }
-    let (log, err_log) = loggers(&format!("{}.video", info.id()), context.logging);
-    let status = Command::new(External::YtDlp.command())
-        .args([
-            "-N",
-            &context.threads.to_string(),
-            "--compat-options",
-            "filename",
-            "--downloader",
-            "m3u8:ffmpeg",
-            &url,
-        ])
-        .stdout(log)
-        .stderr(err_log)
-        .status()?;
-    if !status.success() {
-        return Err(Error::CommandFailed(External::YtDlp));
-    }
+    let (log, err_log) = loggers(dir.join(format!("{}.video", info.id())), context.logging);
+    retry::status(
+        External::YtDlp,
+        command(&External::YtDlp, dir)
+            .args([
+                "-N",
+                &context.threads.to_string(),
+                "--compat-options",
+                "filename",
+                "--downloader",
+                "m3u8:ffmpeg",
+                &url,
+            ])
+            .stdout(log)
+            .stderr(err_log),
+        context,
+    )?;
     Ok(())
 }
 
@@ -269,12 +314,56 @@ pub(crate) struct Video {
     pub(super) duration: String,
     #[serde(default)]
     pub(super) muted_segments: Option<Vec<MutedSegments>>,
+    #[serde(skip)]
+    pub(super) raw: Value,
 }
 
+/// A game played during a VOD, kept in the `moments` array of `{id}.json`.
 #[derive(Debug, Deserialize, Serialize, Clone)]
-pub(super) struct MutedSegments {
-    duration: u64,
-    offset: u64,
+pub(crate) struct Moment {
+    pub(crate) game_id: String,
+    pub(crate) game: String,
+    pub(crate) box_art_url: String,
+    /// Seconds from the start of the video
+    pub(crate) start: f64,
+    pub(crate) duration: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct MutedSegments {
+    pub(crate) duration: u64,
+    pub(crate) offset: u64,
+}
+
+#[cfg(test)]
+impl Video {
+    /// A one hour VOD muted at the given `(offset, duration)` segments.
+    pub(crate) fn test(id: &str, muted: &[(u64, u64)]) -> Self {
+        let segments: Vec<Value> = muted
+            .iter()
+            .map(|(offset, duration)| serde_json::json!({ "offset": offset, "duration": duration }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "stream_id": null,
+            "user_id": "42",
+            "user_login": "channel",
+            "user_name": "Channel",
+            "title": format!("Video {id}"),
+            "description": "",
+            "created_at": "2024-01-01T00:00:00Z",
+            "published_at": "2024-01-01T00:00:00Z",
+            "url": format!("https://www.twitch.tv/videos/{id}"),
+            "thumbnail_url": "",
+            "viewable": "public",
+            "view_count": 1,
+            "language": "en",
+            "type": "archive",
+            "duration": "1h0m0s",
+            "muted_segments": segments,
+        }))
+        .unwrap()
+    }
 }
 
 impl VideoInfo for Video {
@@ -290,6 +379,34 @@ impl VideoInfo for Video {
     fn to_video(&self) -> Video {
         self.clone()
     }
+    fn metadata(&self) -> VideoMetadata {
+        let live = self.r#type == "live";
+        VideoMetadata {
+            schema_version: SCHEMA_VERSION,
+            platform: "twitch",
+            kind: self.r#type.clone(),
+            id: self.id.clone(),
+            title: self.title.clone(),
+            description: Some(self.description.clone()),
+            channel_id: self.user_id.clone(),
+            channel_login: Some(self.user_login.clone()),
+            channel_name: self.user_name.clone(),
+            created_at: self.created_at.clone(),
+            published_at: Some(self.published_at.clone()),
+            url: self.url.clone(),
+            thumbnail_url: self.thumbnail_url.clone(),
+            duration: chapters::seconds(&self.duration),
+            // Live streams only have a viewer count
+            view_count: (!live).then_some(self.view_count),
+            language: Some(self.language.clone()),
+            chapters: Vec::new(),
+            moments: None,
+            extra: self.raw.clone(),
+        }
+    }
+    fn set_raw(&mut self, raw: Value) {
+        self.raw = raw;
+    }
 }
 
 impl Display for Video {
@@ -314,14 +431,60 @@ impl From<YtVideo> for Video {
             thumbnail_url: video.thumbnail_url().to_string(),
             viewable: "true".to_string(),
             view_count: video.statistics.view_count.parse().unwrap_or(0),
-            language: video.snippet.default_language.to_string(),
+            language: video
+                .snippet
+                .default_language
+                .clone()
+                .unwrap_or_else(|| "en".to_string()),
             r#type: "youtube".to_string(),
             duration: video.content_details.duration.to_string(),
             muted_segments: None,
+            raw: video.raw,
         }
     }
 }
 
+impl From<Stream> for Video {
+    fn from(stream: Stream) -> Self {
+        Video {
+            id: stream.id.to_string(),
+            stream_id: Some(stream.id),
+            url: format!("https://www.twitch.tv/{}", stream.user_login),
+            user_id: stream.user_id,
+            user_login: stream.user_login,
+            user_name: stream.user_name,
+            title: stream.title,
+            description: String::new(),
+            created_at: stream.started_at.to_string(),
+            published_at: stream.started_at,
+            thumbnail_url: stream
+                .thumbnail_url
+                .replace("{width}", "%{width}")
+                .replace("{height}", "%{height}"),
+            viewable: "public".to_string(),
+            view_count: stream.viewer_count,
+            language: stream.language,
+            r#type: "live".to_string(),
+            duration: String::new(),
+            muted_segments: None,
+            raw: Value::Null,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct Stream {
+    id: String,
+    user_id: String,
+    user_login: String,
+    user_name: String,
+    title: String,
+    viewer_count: u64,
+    started_at: String,
+    language: String,
+    thumbnail_url: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct User {
     id: String,
@@ -332,8 +495,8 @@ fn channel_regex(channel: &str) -> bool {
     RE.is_match(channel).unwrap_or(false)
 }
 
-fn get_channel_request(identifier: &str, context: &mut Context) -> Result<ChannelData, Error> {
-    let url = format!("https://api.twitch.tv/helix/users?{identifier}");
+fn get_channel_request(identifier: &str, context: &Context) -> Result<ChannelData, Error> {
+    let url = format!("{}/users?{identifier}", context.endpoints.twitch_helix);
     let response = get(&url, context)?;
     message(&format!("[channel_type] Response: {response}"), context, 3);
     let user_response: TwitchResponse<User> = serde_json::from_str(&response)?;
@@ -357,7 +520,7 @@ fn get_channel_request(identifier: &str, context: &mut Context) -> Result<Channe
     }
 }
 
-pub(super) fn get_channel(channel: &str, context: &mut Context) -> Result<ChannelData, Error> {
+pub(super) fn get_channel(channel: &str, context: &Context) -> Result<ChannelData, Error> {
     let channel = common::regex_helper(channel, context, &[&CHANNEL])?;
     let id = format!("id={channel}");
     let login = format!("login={channel}");
@@ -371,11 +534,42 @@ pub(super) fn get_channel(channel: &str, context: &mut Context) -> Result<Channe
     }
 }
 
-pub(super) fn get(url: &str, context: &mut Context) -> Result<String, Error> {
-    Ok((context.client)
-        .get(url)
-        .header("Client-ID", &context.client_id)
-        .bearer_auth(&context.token)
-        .send()?
-        .text()?)
+/// The Helix `videos` or `clips` among `ids` that exist. Helix answers 404 for
+/// a whole request of videos when any of them is gone, while it leaves missing
+/// clips out, so the ids of such a request are looked up one at a time.
+pub(super) fn lookup(endpoint: &str, ids: &[&str], context: &Context) -> Result<Vec<Value>, Error> {
+    let mut found = Vec::new();
+    for batch in ids.chunks(BATCH) {
+        let query: Vec<String> = batch.iter().map(|id| format!("id={id}")).collect();
+        let url = format!(
+            "{}/{endpoint}?{}",
+            context.endpoints.twitch_helix,
+            query.join("&")
+        );
+        message(&format!("[lookup] URL: {url}"), context, 3);
+        let mut response: Value = serde_json::from_str(&get(&url, context)?)?;
+        message(&format!("[lookup] Response: {response}"), context, 4);
+        match response.get_mut("data").map(Value::take) {
+            Some(Value::Array(data)) => found.extend(data),
+            _ if response.get("status").and_then(Value::as_u64) == Some(404) => {
+                if batch.len() > 1 {
+                    for id in batch {
+                        found.extend(lookup(endpoint, slice::from_ref(id), context)?);
+                    }
+                }
+            }
+            _ => return Err(Error::Api(api_message(&response))),
+        }
+    }
+    Ok(found)
+}
+
+pub(super) fn get(url: &str, context: &Context) -> Result<String, Error> {
+    Ok(retry::send(context, || {
+        (context.client)
+            .get(url)
+            .header("Client-ID", &context.client_id)
+            .bearer_auth(&context.token)
+    })?
+    .text()?)
 }
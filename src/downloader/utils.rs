@@ -1,18 +1,24 @@
-use super::{twitch::Video, Context};
+use super::metadata::VideoMetadata;
+use super::{retry, twitch::Video, Context, External};
 use crate::Error;
 use colored::{Color, Colorize};
 use fancy_regex::{Captures, Regex};
-use serde::de::DeserializeOwned;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::Serialize;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt::{self, Debug, Display, Formatter};
-use std::fs::{File, OpenOptions};
-use std::io::Write;
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::sync::LazyLock;
+use std::sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
 use strfmt::strfmt;
-use terminal_spinners::{SpinnerBuilder, SpinnerHandle, DOTS2};
+use terminal_spinners::DOTS2;
 use unicode_general_category::{get_general_category, GeneralCategory};
 use unicode_normalization::UnicodeNormalization;
 
@@ -38,11 +44,23 @@ static SYMBOLS: LazyLock<HashMap<char, &str>> = LazyLock::new(|| {
     "\"*:<>?|/\\".chars().zip(replacements).collect()
 });
 
-pub(crate) trait VideoInfo: Debug + Display + Serialize + DeserializeOwned {
+pub(crate) trait VideoInfo:
+    Debug + Display + Serialize + DeserializeOwned + Send + Sync
+{
     fn title(&self) -> &str;
     fn id(&self) -> &str;
     fn thumbnail_url(&self) -> &str;
     fn to_video(&self) -> Video;
+    fn metadata(&self) -> VideoMetadata;
+    /// Keeps the API response the video was read from.
+    fn set_raw(&mut self, raw: Value);
+}
+
+/// Reads a video from an API response, keeping the response for its metadata.
+pub(crate) fn from_raw<T: VideoInfo>(raw: Value) -> Result<T, serde_json::Error> {
+    let mut info = T::deserialize(&raw)?;
+    info.set_raw(raw);
+    Ok(info)
 }
 
 pub(super) fn split_videos(data: &str) -> Vec<String> {
@@ -62,11 +80,9 @@ pub(super) fn colorize(label: Option<&str>, message: &str, color: Color) -> Stri
     }
 }
 
-pub(super) fn message(msg: &str, context: &mut Context, threshold: i16) {
+pub(super) fn message(msg: &str, context: &Context, threshold: i16) {
     if context.verbosity >= threshold {
-        context.spinner.stop();
-        println!("{msg}");
-        context.spinner.start();
+        context.spinner.println(msg);
     }
 }
 
@@ -76,7 +92,7 @@ pub(super) fn help_error(message: &str, extra: Option<&[&str]>) {
     eprintln!("\nFor more information try {}", "archiver --help".green());
 }
 
-pub(super) fn good_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &mut Context) {
+pub(super) fn good_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &Context) {
     message(
         &colorize(label, &msg.into(), Color::BrightGreen),
         context,
@@ -84,7 +100,7 @@ pub(super) fn good_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &m
     );
 }
 
-pub(super) fn warn_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &mut Context) {
+pub(super) fn warn_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &Context) {
     message(
         &colorize(label, &msg.into(), Color::BrightYellow),
         context,
@@ -92,7 +108,7 @@ pub(super) fn warn_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &m
     );
 }
 
-pub(super) fn error_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &mut Context) {
+pub(super) fn error_msg<T: Into<String>>(label: Option<&str>, msg: T, context: &Context) {
     message(&colorize(label, &msg.into(), Color::BrightRed), context, -1);
 }
 
@@ -105,8 +121,9 @@ pub(crate) fn error(message: &str, extra: Option<&[&str]>) {
     }
 }
 
-pub(crate) fn loggers(name: &str, enabled: bool) -> (Stdio, Stdio) {
+pub(crate) fn loggers(name: impl AsRef<Path>, enabled: bool) -> (Stdio, Stdio) {
     if enabled {
+        let name = name.as_ref().display();
         let log_string = format!("{name}.log");
         let log = OpenOptions::new()
             .append(true)
@@ -125,24 +142,135 @@ pub(crate) fn loggers(name: &str, enabled: bool) -> (Stdio, Stdio) {
     }
 }
 
+pub(super) fn command(program: &External, dir: &Path) -> Command {
+    let mut command = Command::new(program.command());
+    if !dir.as_os_str().is_empty() {
+        command.current_dir(dir);
+    }
+    command
+}
+
+pub(super) fn part_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.part.{}", ext.to_string_lossy()),
+        None => format!("{stem}.part"),
+    };
+    path.with_file_name(name)
+}
+
+pub(super) fn verified(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    if !fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0) {
+        return false;
+    }
+    if path.extension().is_some_and(|ext| ext == "json") {
+        return File::open(path).is_ok_and(|file| {
+            serde_json::from_reader::<_, IgnoredAny>(BufReader::new(file)).is_ok()
+        });
+    }
+    true
+}
+
+pub(super) fn remove_part(path: impl AsRef<Path>) -> Result<(), Error> {
+    let part = part_path(path);
+    match fs::remove_file(&part) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::io(err, part)),
+        _ => Ok(()),
+    }
+}
+
+pub(super) fn finish_part(path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let part = part_path(path);
+    if !verified(&part) {
+        remove_part(path)?;
+        return Err(Error::Incomplete(part));
+    }
+    fs::rename(&part, path).map_err(|err| Error::io(err, path))
+}
+
 pub(super) fn write_file(path: impl AsRef<Path>, bytes: &[u8]) -> Result<(), Error> {
     let path = path.as_ref();
-    if path.exists() {
-        return Err(Error::AlreadyExists);
+    let part = part_path(path);
+    let mut file = File::create(&part).map_err(|err| Error::io(err, &part))?;
+    file.write_all(bytes)
+        .and_then(|()| file.sync_all())
+        .map_err(|err| Error::io(err, &part))?;
+    finish_part(path)
+}
+
+/// `to` relative to the directory `from`, both resolved from the working directory.
+pub(super) fn relative(from: &Path, to: &Path) -> Option<PathBuf> {
+    let from = std::path::absolute(from).ok()?;
+    let to = std::path::absolute(to).ok()?;
+    let from: Vec<Component> = from
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+    let to: Vec<Component> = to
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        return Some(to.iter().collect());
     }
-    let mut file = File::create(path)?;
-    file.write_all(bytes)?;
-    Ok(())
+    let mut path: PathBuf = from[common..].iter().map(|_| "..").collect();
+    path.extend(&to[common..]);
+    Some(path)
+}
+
+/// The error message of a Helix or YouTube response.
+pub(super) fn api_message(response: &Value) -> String {
+    ["/message", "/error/message"]
+        .iter()
+        .find_map(|pointer| response.pointer(pointer)?.as_str())
+        .unwrap_or("Unexpected response")
+        .to_string()
 }
 
 pub(super) fn download_file(
     path: impl AsRef<Path>,
     url: &str,
-    context: &mut Context,
+    context: &Context,
 ) -> Result<(), Error> {
-    let response = context.client.get(url).send()?;
-    let bytes = response.bytes()?;
-    write_file(path, &bytes)
+    let path = path.as_ref();
+    retry::run(context, || resume_file(path, url, context))
+}
+
+fn resume_file(path: &Path, url: &str, context: &Context) -> Result<(), Error> {
+    let part = part_path(path);
+    let offset = fs::metadata(&part).map_or(0, |metadata| metadata.len());
+    let mut request = context.client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let response = request.send()?;
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The part is complete when its size is the total in `Content-Range: bytes */<total>`
+        let total = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.strip_prefix("bytes */"))
+            .and_then(|total| total.parse::<u64>().ok());
+        if total == Some(offset) {
+            return finish_part(path);
+        }
+        fs::remove_file(&part).map_err(|err| Error::io(err, &part))?;
+        return resume_file(path, url, context);
+    }
+    let mut response = response.error_for_status()?;
+    let mut file = match response.status() {
+        StatusCode::PARTIAL_CONTENT => OpenOptions::new().append(true).open(&part),
+        _ => File::create(&part),
+    }
+    .map_err(|err| Error::io(err, &part))?;
+    response.copy_to(&mut file)?;
+    file.sync_all().map_err(|err| Error::io(err, &part))?;
+    finish_part(path)
 }
 
 fn is_cm(c: char) -> bool {
@@ -215,74 +343,208 @@ pub(super) fn sanitize(data: String, restricted: bool) -> String {
 }
 
 pub(crate) struct Spinner {
-    handle: Option<SpinnerHandle>,
-    message: Option<String>,
+    state: Arc<Mutex<SpinnerState>>,
     verbosity: i16,
     hidden: bool,
 }
 
+#[derive(Debug, Default)]
+struct SpinnerState {
+    lines: Vec<(ThreadId, String)>,
+    drawn: usize,
+    frame: usize,
+    rendering: bool,
+}
+
 impl Debug for Spinner {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let handle = match &self.handle {
-            Some(_) => "Some",
-            None => "None",
-        };
-        write!(
-            f,
-            "Spinner {{ handle: {handle}, message: {:?} }}",
-            self.message
-        )
+        let state = lock(&self.state);
+        let messages: Vec<&String> = state.lines.iter().map(|(_, line)| line).collect();
+        write!(f, "Spinner {{ messages: {messages:?} }}")
     }
 }
 
 impl Spinner {
     pub(crate) fn new(verbosity: i16, hidden: bool) -> Self {
         Self {
-            handle: None,
-            message: None,
+            state: Arc::default(),
             verbosity,
             hidden,
         }
     }
-    pub(crate) fn create(&mut self, message: &str) {
-        if self.verbosity >= -1 && !self.hidden {
-            self.message = Some(message.to_string());
-            self.handle = Some(
-                SpinnerBuilder::new()
-                    .spinner(&DOTS2)
-                    .text(message.to_string())
-                    .start(),
-            );
+    pub(crate) fn create(&self, message: &str) {
+        if self.verbosity < -1 || self.hidden {
+            return;
+        }
+        let thread = thread::current().id();
+        let mut state = lock(&self.state);
+        match state.lines.iter_mut().find(|(id, _)| *id == thread) {
+            Some((_, line)) => *line = message.to_string(),
+            None => state.lines.push((thread, message.to_string())),
+        }
+        state.draw();
+        if !state.rendering {
+            state.rendering = true;
+            let state = Arc::clone(&self.state);
+            thread::spawn(move || render(&state));
         }
     }
-    pub(crate) fn start(&mut self) {
-        if self.verbosity >= -1 {
-            if let Some(msg) = self.message.clone() {
-                self.create(&msg);
-            }
+    pub(crate) fn println(&self, message: &str) {
+        let mut state = lock(&self.state);
+        state.clear();
+        println!("{message}");
+        state.draw();
+    }
+    pub(crate) fn end(&self) {
+        let thread = thread::current().id();
+        let mut state = lock(&self.state);
+        state.lines.retain(|(id, _)| *id != thread);
+        state.draw();
+    }
+}
+
+impl SpinnerState {
+    fn clear(&mut self) {
+        if self.drawn > 0 {
+            print!("\x1b[{}A\x1b[J", self.drawn);
+            self.drawn = 0;
+        }
+    }
+    fn draw(&mut self) {
+        if self.drawn == 0 && self.lines.is_empty() {
+            return;
+        }
+        let mut stdout = io::stdout().lock();
+        let mut output = String::new();
+        if self.drawn > 0 {
+            output.push_str(&format!("\x1b[{}A", self.drawn));
+        }
+        output.push_str("\x1b[J");
+        let frame = DOTS2.frames[self.frame];
+        for (_, line) in &self.lines {
+            output.push_str(&format!("{frame}{line}\n"));
+        }
+        self.drawn = self.lines.len();
+        let _ = stdout.write_all(output.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+fn render(state: &Mutex<SpinnerState>) {
+    loop {
+        thread::sleep(Duration::from_millis(DOTS2.interval));
+        let mut state = lock(state);
+        if state.lines.is_empty() {
+            state.rendering = false;
+            return;
+        }
+        state.frame = (state.frame + 1) % DOTS2.frames.len();
+        state.draw();
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Semaphore {
+    permits: Mutex<usize>,
+    available: Condvar,
+}
+
+pub(crate) struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            available: Condvar::new(),
         }
     }
-    pub(crate) fn stop(&mut self) {
-        if let Some(handle) = self.handle.take() {
-            handle.stop_and_clear();
+    pub(crate) fn acquire(&self) -> Permit<'_> {
+        let mut permits = lock(&self.permits);
+        while *permits == 0 {
+            permits = self
+                .available
+                .wait(permits)
+                .unwrap_or_else(PoisonError::into_inner);
         }
+        *permits -= 1;
+        Permit { semaphore: self }
     }
-    pub(crate) fn end(&mut self) {
-        self.stop();
-        self.message = None;
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *lock(&self.semaphore.permits) += 1;
+        self.semaphore.available.notify_one();
     }
 }
 
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 pub(crate) fn run_template(template: &str, vars: &HashMap<String, String>) -> Result<(), Error> {
-    match Command::new("bash")
+    let status = Command::new("bash")
         .arg("-c")
         .arg(&strfmt(template, vars)?)
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
-        .status()?
-        .success()
-    {
+        .status()?;
+    match status.success() {
         true => Ok(()),
-        false => Err(Error::Template),
+        false => Err(Error::Template(status.to_string())),
     }
 }
+
+/// An empty directory named after a test, for the files it writes.
+#[cfg(test)]
+pub(crate) fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("archiver-{test}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Answers one request with each of `responses` in order on a local port,
+/// returning its URL and a handle to the paths and bodies of the requests it got.
+#[cfg(test)]
+pub(crate) fn serve(responses: Vec<String>) -> (String, thread::JoinHandle<Vec<(String, String)>>) {
+    use std::io::{BufRead, Read};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            requests.push((path, String::from_utf8(body).unwrap()));
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                response.len()
+            )
+            .unwrap();
+        }
+        requests
+    });
+    (url, handle)
+}
@@ -1,4 +1,9 @@
-use super::{twitch::Video, utils::loggers, Context};
+use super::metadata::{VideoMetadata, SCHEMA_VERSION};
+use super::report::Reason;
+use super::utils::{
+    command, finish_part, from_raw, loggers, part_path, remove_part, verified, warn_msg,
+};
+use super::{chapters, chat, retry, twitch::Video, Context, VideoType};
 use crate::downloader::common;
 use crate::init::external::External;
 use crate::utils::{colorize, message, sanitize, VideoInfo};
@@ -9,9 +14,9 @@ use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::cmp::min;
+use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
 use std::path::Path;
-use std::process::Command;
 use std::sync::LazyLock;
 
 static ID_REGEX: LazyLock<Regex> =
@@ -26,43 +31,78 @@ static CHAN_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| unsafe {
         .unwrap_unchecked()
 });
 
-pub(super) fn download<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
+pub(super) fn download<T: VideoInfo>(info: &T, context: &Context) -> Result<(), Error> {
     common::download(
         info,
         context,
         save_json,
         common::get_thumbnail,
         get_chat,
-        process_chat,
+        chat::process,
         get_video,
     )
 }
 
-pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &mut Context) -> Result<Vec<T>, Error> {
+pub(super) fn get_ids<T: VideoInfo>(data: &str, context: &Context) -> Result<Vec<T>, Error> {
     common::get_ids(data, "", context, &[&ID_REGEX, &URL_REGEX], id2info)
 }
 
-fn id2info<T: VideoInfo>(ids: &[String], _t: &str, context: &mut Context) -> Result<Vec<T>, Error> {
+fn id2info<T: VideoInfo>(
+    ids: &[String],
+    _t: &str,
+    context: &Context,
+    missing: &mut Vec<(String, Reason)>,
+) -> Result<Vec<T>, Error> {
+    // oEmbed also answers 401 for public videos that cannot be embedded, so its
+    // status only explains the ids that the Data API does not return either
+    let mut statuses = HashMap::new();
     let ids = ids
         .iter()
         .filter_map(|id| {
             let url = format!(
         "https://www.youtube.com/oembed?format=json&url=http://www.youtube.com/watch?v={id}"
       );
-            let status = get_status(&url, context);
-            if let Ok(status) = status {
-                if status == StatusCode::OK {
-                    return Some(id.to_string());
-                }
+            let status = get_status(&url, context).ok();
+            if status == Some(StatusCode::BAD_REQUEST) {
+                missing.push((id.to_string(), Reason::Invalid));
+                return None;
             }
-            None
+            statuses.insert(id.to_string(), status);
+            Some(id.to_string())
         })
         .collect::<Vec<String>>();
-    get_info(ids, context)
+    let info: Vec<T> = get_info(ids.clone(), context)?;
+    let (blocked, info): (Vec<T>, Vec<T>) = info.into_iter().partition(|info| {
+        // Only videos allowed in no region are known to be blocked, the ones
+        // blocked in some regions are tried and fail like any other download
+        info.metadata()
+            .extra
+            .pointer("/contentDetails/regionRestriction/allowed")
+            .and_then(Value::as_array)
+            .is_some_and(Vec::is_empty)
+    });
+    missing.extend(
+        blocked
+            .iter()
+            .map(|info| (info.id().to_string(), Reason::RegionBlocked)),
+    );
+    for id in ids {
+        if info.iter().any(|info| info.id() == id) || missing.iter().any(|(m, _)| *m == id) {
+            continue;
+        }
+        let reason = match statuses.get(&id).copied().flatten() {
+            // Public videos the API leaves out are still live, upcoming, or processing
+            Some(StatusCode::OK) => Reason::Processing,
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => Reason::Private,
+            Some(StatusCode::NOT_FOUND) => Reason::Deleted,
+            _ => Reason::Unavailable,
+        };
+        missing.push((id, reason));
+    }
+    Ok(info)
 }
 
-#[allow(clippy::unnecessary_wraps)]
-fn get_info<T: VideoInfo>(mut ids: Vec<String>, context: &mut Context) -> Result<Vec<T>, Error> {
+fn get_info<T: VideoInfo>(mut ids: Vec<String>, context: &Context) -> Result<Vec<T>, Error> {
     let max = 50;
     let mut info = Vec::new();
     while !ids.is_empty() {
@@ -75,28 +115,31 @@ fn get_info<T: VideoInfo>(mut ids: Vec<String>, context: &mut Context) -> Result
         message(&format!("[get_info] Query {query}"), context, 3);
         let url = format!("https://youtube.googleapis.com/youtube/v3/videos?part=snippet%2CcontentDetails%2Cstatistics&id={query}&maxResults={max}&key={}", context.token);
         message(&format!("[get_info] URL: {url}"), context, 3);
-        if let Ok(response) = get(&url, context) {
-            message(&format!("[get_info] Response: {response}"), context, 3);
-            match serde_json::from_str::<YtResponse<Value>>(&response) {
-                Ok(data) => {
-                    for video in data.items {
-                        if past_video(&video) {
-                            if let Ok(video) = serde_json::from_value::<T>(video) {
-                                info.push(video);
-                            }
+        let response = match get(&url, context) {
+            Ok(response) => response,
+            Err(err @ (Error::Quota(_) | Error::Request(_))) => return Err(err),
+            Err(_) => continue,
+        };
+        message(&format!("[get_info] Response: {response}"), context, 3);
+        match serde_json::from_str::<YtResponse<Value>>(&response) {
+            Ok(data) => {
+                for video in data.items {
+                    if past_video(&video, context) {
+                        if let Ok(video) = from_raw::<T>(video) {
+                            info.push(video);
                         }
                     }
                 }
-                Err(err) => message(
-                    &colorize(
-                        Some("get_info"),
-                        &format!("JSON Error: {err}"),
-                        Color::BrightRed,
-                    ),
-                    context,
-                    -1,
-                ),
             }
+            Err(err) => message(
+                &colorize(
+                    Some("get_info"),
+                    &format!("JSON Error: {err}"),
+                    Color::BrightRed,
+                ),
+                context,
+                -1,
+            ),
         }
     }
     message("[get_info] Info found:", context, 3);
@@ -106,16 +149,20 @@ fn get_info<T: VideoInfo>(mut ids: Vec<String>, context: &mut Context) -> Result
     Ok(info)
 }
 
-fn past_video(video: &Value) -> bool {
+fn past_video(video: &Value, context: &Context) -> bool {
     let Some(snippet) = video.get("snippet") else {
         return false;
     };
     let Some(live) = snippet.get("liveBroadcastContent") else {
         return false;
     };
+    let wanted = match context.downloader {
+        VideoType::YouTubeLive => "live",
+        _ => "none",
+    };
     live.is_string()
         && if let Some(value) = live.as_str() {
-            value == "none"
+            value == wanted
         } else {
             false
         }
@@ -123,7 +170,7 @@ fn past_video(video: &Value) -> bool {
 
 pub(super) fn get_channel_ids<T: VideoInfo>(
     channel: &str,
-    context: &mut Context,
+    context: &Context,
 ) -> Result<Vec<T>, Error> {
     let max = 50;
     let channel = common::regex_helper(channel, context, &[&CHAN_REGEX, &CHAN_URL_REGEX])?;
@@ -166,18 +213,50 @@ pub(super) fn get_channel_ids<T: VideoInfo>(
                 return Err(err.into());
             }
         };
-        for video in data.items {
-            videos.push(video.snippet.resource_id.video_id.to_string());
+        let ids = data
+            .items
+            .iter()
+            .map(|video| video.snippet.resource_id.video_id.to_string())
+            .collect();
+        // Uploads are listed newest first, so the filters can end the listing early
+        if !context
+            .filter
+            .extend(&mut videos, get_info(ids, context)?, context)
+        {
+            break;
         }
         match data.next_page_token {
             Some(cursor) if cursor != after || after.is_empty() => after = cursor,
             _ => break,
         }
     }
-    get_info(videos, context)
+    Ok(videos)
 }
 
-fn get_channel(channel: &str, context: &mut Context) -> Result<Channel, Error> {
+pub(super) fn get_live_ids<T: VideoInfo>(
+    channel: &str,
+    context: &Context,
+) -> Result<Vec<T>, Error> {
+    let channel = common::regex_helper(channel, context, &[&CHAN_REGEX, &CHAN_URL_REGEX])?;
+    let user = get_channel(&channel, context)?;
+    let url = format!(
+      "https://youtube.googleapis.com/youtube/v3/search?part=id&channelId={}&eventType=live&type=video&key={}", user.id, context.token
+    );
+    let response = get(&url, context)?;
+    message(&format!("[get_live_ids] Response: {response}"), context, 3);
+    let data: YtResponse<SearchResult> = serde_json::from_str(&response)?;
+    if data.items.is_empty() {
+        message(&format!("[get_live_ids] {channel} is not live"), context, 1);
+    }
+    let ids = data
+        .items
+        .into_iter()
+        .map(|item| item.id.video_id)
+        .collect();
+    get_info(ids, context)
+}
+
+fn get_channel(channel: &str, context: &Context) -> Result<Channel, Error> {
     let url = format!(
     "https://youtube.googleapis.com/youtube/v3/channels?part=contentDetails&id={channel}&key={}",
     context.token
@@ -196,85 +275,145 @@ fn get_channel(channel: &str, context: &mut Context) -> Result<Channel, Error> {
     Err(Error::NoMatches)
 }
 
-fn save_json<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
-    let info: Video = info.to_video();
-    common::save_json(&info, context)
+pub(super) fn save_json<T: VideoInfo>(
+    info: &T,
+    dir: &Path,
+    context: &Context,
+) -> Result<(), Error> {
+    let path = dir.join(format!("{}.json", info.id()));
+    if verified(&path) {
+        return Err(Error::AlreadyExists);
+    }
+    let mut metadata = info.metadata();
+    let description = metadata.description.as_deref().unwrap_or_default();
+    metadata.chapters = chapters::from_description(description, metadata.duration);
+    metadata.write(&path)?;
+    if let Err(err) = chapters::sidecars(dir, &metadata.id, &metadata.chapters, metadata.duration) {
+        warn_msg(
+            Some("chapters"),
+            format!(
+                "Could not write the chapters of {}: {}",
+                metadata.id,
+                err.describe()
+            ),
+            context,
+        );
+    }
+    Ok(())
 }
 
-fn get_chat(id: &str, context: &mut Context) -> Result<(), Error> {
+fn get_chat(id: &str, dir: &Path, context: &Context) -> Result<(), Error> {
     let chat_string = format!("{id}.chat.json");
-    let chat = Path::new(&chat_string);
-    if chat.exists() {
+    let chat = &dir.join(&chat_string);
+    if context.compression.exists(chat) {
         return Err(Error::AlreadyExists);
     }
     if context.missing.contains(&External::Cd) {
         return Err(Error::MissingProgram(External::Cd));
     }
-    let (log, err_log) = loggers(&format!("{id}.chat"), context.logging);
-    let status = Command::new(External::Cd.command())
-        .args([
-            &format!("https://www.youtube.com/watch?v={id}"),
-            "--output",
-            &chat_string,
-        ])
-        .stdout(log)
-        .stderr(err_log)
-        .status()?;
-    if !status.success() {
-        return Err(Error::CommandFailed(External::Cd));
-    }
-    Ok(())
-}
-
-fn process_chat(_chat: &str, _context: &mut Context) -> Result<(), Error> {
-    Err(Error::Expected)
+    remove_part(chat)?;
+    let (log, err_log) = loggers(dir.join(format!("{id}.chat")), context.logging);
+    retry::status(
+        External::Cd,
+        command(&External::Cd, dir)
+            .args([&format!("https://www.youtube.com/watch?v={id}"), "--output"])
+            .arg(part_path(&chat_string))
+            .stdout(log)
+            .stderr(err_log),
+        context,
+    )?;
+    finish_part(chat)
 }
 
-fn get_video<T: VideoInfo>(info: &T, context: &mut Context) -> Result<(), Error> {
-    let video_filename = filename(info.title().to_string(), info.id());
-    let video = Path::new(&video_filename);
+fn get_video<T: VideoInfo>(info: &T, dir: &Path, context: &Context) -> Result<(), Error> {
+    let video = &dir.join(filename(info.title().to_string(), info.id()));
     let url = format!("https://youtube.com/watch?v={}", info.id());
-    if video.exists() {
+    if verified(video) {
         return Err(Error::AlreadyExists);
     }
-    let (log, err_log) = loggers(&format!("{}.video", info.id()), context.logging);
+    let (log, err_log) = loggers(dir.join(format!("{}.video", info.id())), context.logging);
     if context.missing.contains(&External::YtDlp) {
         return Err(Error::MissingProgram(External::YtDlp));
     }
-    let status = Command::new(External::YtDlp.command())
-        .args([
-            "-N",
-            &context.threads.to_string(),
-            "--compat-options",
-            "filename",
-            &url,
-        ])
-        .stdout(log)
-        .stderr(err_log)
-        .status()?;
-    if !status.success() {
-        return Err(Error::CommandFailed(External::YtDlp));
-    }
+    retry::status(
+        External::YtDlp,
+        command(&External::YtDlp, dir)
+            .args([
+                "-N",
+                &context.threads.to_string(),
+                "--compat-options",
+                "filename",
+                &url,
+            ])
+            .stdout(log)
+            .stderr(err_log),
+        context,
+    )?;
     Ok(())
 }
 
-fn filename(title: String, id: &str) -> String {
+pub(super) fn filename(title: String, id: &str) -> String {
     let title = sanitize(title, false);
     let filename = format!("{title}-{id}.mp4");
     filename
 }
 
-fn get_status(url: &str, context: &mut Context) -> Result<StatusCode, Error> {
-    Ok(context.client.get(url).send()?.status())
+fn get_status(url: &str, context: &Context) -> Result<StatusCode, Error> {
+    Ok(retry::send(context, || context.client.get(url))?.status())
+}
+
+pub(super) fn get(url: &str, context: &Context) -> Result<String, Error> {
+    context.rate_limit.spend(quota_cost(url));
+    let response = retry::send(context, || {
+        context.client.get(url).header("Accept", "application/json")
+    })?;
+    let status = response.status();
+    let text = response.text()?;
+    if status == StatusCode::FORBIDDEN {
+        if let Ok(YtError { error }) = serde_json::from_str::<YtError>(&text) {
+            if error.errors.iter().any(|reason| {
+                matches!(
+                    reason.reason.as_str(),
+                    "quotaExceeded" | "dailyLimitExceeded" | "rateLimitExceeded"
+                )
+            }) {
+                return Err(Error::Quota(format!(
+                    "YouTube API quota exceeded after an estimated {} units this run: {}",
+                    context.rate_limit.youtube_units(),
+                    error.message
+                )));
+            }
+        }
+    }
+    Ok(text)
 }
 
-fn get(url: &str, context: &mut Context) -> Result<String, Error> {
-    Ok(context
-        .client
-        .get(url)
-        .header("Accept", "application/json")
-        .send()?
-        .text()?)
+/// Estimated YouTube Data API quota units used by a request.
+fn quota_cost(url: &str) -> u64 {
+    if !url.contains("youtube.googleapis.com/youtube/v3/") {
+        0
+    } else if url.contains("/youtube/v3/search") {
+        100
+    } else {
+        1
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct YtError {
+    error: YtErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct YtErrorBody {
+    message: String,
+    #[serde(default)]
+    errors: Vec<YtErrorReason>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YtErrorReason {
+    reason: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -318,6 +457,12 @@ struct PlaylistItem {
     snippet: ItemSnippet,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SearchResult {
+    id: ResourceId,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct ItemSnippet {
@@ -337,6 +482,8 @@ pub(crate) struct YtVideo {
     pub(super) snippet: YtSnippet,
     pub(super) content_details: YtContentDetails,
     pub(super) statistics: YtStatistics,
+    #[serde(skip)]
+    pub(super) raw: Value,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -349,8 +496,8 @@ pub(super) struct YtSnippet {
     thumbnails: YtThumbnails,
     live_broadcast_content: String,
     pub(super) channel_title: String,
-    #[serde(default = "default_lang")]
-    pub(super) default_language: String,
+    #[serde(default)]
+    pub(super) default_language: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -409,6 +556,32 @@ impl VideoInfo for YtVideo {
     fn to_video(&self) -> Video {
         self.clone().into()
     }
+    fn metadata(&self) -> VideoMetadata {
+        VideoMetadata {
+            schema_version: SCHEMA_VERSION,
+            platform: "youtube",
+            kind: "video".to_string(),
+            id: self.id.clone(),
+            title: self.snippet.title.clone(),
+            description: Some(self.snippet.description.clone()),
+            channel_id: self.snippet.channel_id.clone(),
+            channel_login: None,
+            channel_name: self.snippet.channel_title.clone(),
+            created_at: self.snippet.published_at.clone(),
+            published_at: Some(self.snippet.published_at.clone()),
+            url: format!("https://www.youtube.com/watch?v={}", self.id),
+            thumbnail_url: self.thumbnail_url().to_string(),
+            duration: chapters::seconds(&self.content_details.duration),
+            view_count: self.statistics.view_count.parse().ok(),
+            language: self.snippet.default_language.clone(),
+            chapters: Vec::new(),
+            moments: None,
+            extra: self.raw.clone(),
+        }
+    }
+    fn set_raw(&mut self, raw: Value) {
+        self.raw = raw;
+    }
 }
 
 impl Display for YtVideo {
@@ -420,7 +593,3 @@ impl Display for YtVideo {
         )
     }
 }
-
-fn default_lang() -> String {
-    "en".to_string()
-}
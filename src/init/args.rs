@@ -1,18 +1,52 @@
+use super::cli::{Chat, Cli, Commands, Refresh, SearchChat};
 use super::{Info, VideoType, Videos};
-use clap::Parser;
-use derive_more::Constructor;
+use clap::{CommandFactory, ErrorKind, Parser};
+use std::path::PathBuf;
 
-#[derive(Debug, Constructor)]
+#[allow(clippy::large_enum_variant)]
+pub(super) enum Parsed {
+    Archive(Args),
+    Chat(Chat),
+    SearchChat(SearchChat),
+    Refresh(Refresh),
+}
+
+#[derive(Debug)]
 pub(super) struct Args {
     pub(super) videos: Videos,
     pub(super) video_type: VideoType,
     pub(super) verbosity: i16,
     pub(crate) hide_spinners: bool,
     pub(super) skip_video: bool,
+    pub(super) output_template: Option<String>,
+    pub(super) reindex: bool,
+    pub(super) every: Option<String>,
+    pub(super) backfill: bool,
+    pub(super) report: Option<PathBuf>,
+    pub(super) fail_on_missing: bool,
     pub(super) logging: bool,
     pub(super) range: String,
     pub(super) interval: String,
+    pub(super) after: Option<String>,
+    pub(super) before: Option<String>,
+    pub(super) min_duration: Option<String>,
+    pub(super) max_duration: Option<String>,
+    pub(super) match_title: Option<String>,
+    pub(super) reject_title: Option<String>,
+    pub(super) min_views: Option<u64>,
+    pub(super) limit: Option<usize>,
+    pub(super) chat_formats: Vec<String>,
+    pub(super) chat_html: bool,
+    pub(super) emotes: bool,
+    pub(super) compression: Option<String>,
+    pub(super) compression_level: Option<u32>,
+    pub(super) delete_uncompressed: bool,
+    pub(super) embed_muted: bool,
+    pub(super) remux: bool,
     pub(super) threads: u16,
+    pub(super) retries: Option<u32>,
+    pub(super) jobs: u16,
+    pub(super) video_jobs: u16,
     pub(super) post_json: Vec<String>,
     pub(super) post_thumbnail: Vec<String>,
     pub(super) post_chat: Vec<String>,
@@ -20,10 +54,29 @@ pub(super) struct Args {
     pub(super) post_video: Vec<String>,
 }
 
-pub(super) fn parse() -> Args {
-    let cli = crate::init::cli::Cli::parse();
+pub(super) fn parse() -> Parsed {
+    let cli = Cli::parse();
+    let (cli, every, backfill) = match cli.command {
+        Some(Commands::Watch(watch)) => {
+            if watch.options.channel.is_none() {
+                Cli::command()
+                    .error(
+                        ErrorKind::MissingRequiredArgument,
+                        "archiver watch requires --channel <CHANNEL> to poll",
+                    )
+                    .exit();
+            }
+            (watch.options, Some(watch.every), watch.backfill)
+        }
+        Some(Commands::Chat(chat)) => return Parsed::Chat(chat),
+        Some(Commands::SearchChat(search)) => return Parsed::SearchChat(search),
+        Some(Commands::Refresh(refresh)) => return Parsed::Refresh(refresh),
+        None => (cli.options, None, false),
+    };
 
     let video_type: VideoType = match (cli.vods, cli.highlights, cli.clips, cli.youtube) {
+        (true, _, _, _) if cli.live => VideoType::TwitchLive,
+        (_, _, _, true) if cli.live => VideoType::YouTubeLive,
         (true, _, _, _) => VideoType::Vod,
         (_, true, _, _) => VideoType::Highlight,
         (_, _, true, _) => VideoType::Clip,
@@ -43,20 +96,45 @@ pub(super) fn parse() -> Args {
         _ => unreachable!(),
     };
     let verbosity = i16::from(cli.verbose) - i16::from(cli.silent);
-    Args::new(
+    Parsed::Archive(Args {
         videos,
         video_type,
         verbosity,
-        cli.hide_spinners,
-        cli.skip_video,
-        cli.logging,
-        cli.range,
-        cli.interval,
-        cli.threads,
-        cli.post_json,
-        cli.post_thumbnail,
-        cli.post_chat,
-        cli.post_chat_process,
-        cli.post_video,
-    )
+        hide_spinners: cli.hide_spinners,
+        skip_video: cli.skip_video,
+        output_template: cli.output_template,
+        reindex: cli.reindex,
+        every,
+        backfill,
+        report: cli.report,
+        fail_on_missing: cli.fail_on_missing,
+        logging: cli.logging,
+        range: cli.range,
+        interval: cli.interval,
+        after: cli.after,
+        before: cli.before,
+        min_duration: cli.min_duration,
+        max_duration: cli.max_duration,
+        match_title: cli.match_title,
+        reject_title: cli.reject_title,
+        min_views: cli.min_views,
+        limit: cli.limit,
+        chat_formats: cli.chat_formats,
+        chat_html: cli.chat_html,
+        emotes: cli.emotes,
+        compression: cli.compression,
+        compression_level: cli.compression_level,
+        delete_uncompressed: cli.delete_uncompressed,
+        embed_muted: cli.embed_muted,
+        remux: cli.remux,
+        threads: cli.threads,
+        retries: cli.retries,
+        jobs: cli.jobs,
+        video_jobs: cli.video_jobs,
+        post_json: cli.post_json,
+        post_thumbnail: cli.post_thumbnail,
+        post_chat: cli.post_chat,
+        post_chat_process: cli.post_chat_process,
+        post_video: cli.post_video,
+    })
 }
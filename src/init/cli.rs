@@ -1,14 +1,185 @@
-use clap::{ArgGroup, Parser};
+use clap::{ArgGroup, Parser, Subcommand};
+use std::path::PathBuf;
 
-#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser)]
 #[clap(
     author,
     version,
     about,
-    override_usage = "archiver [OPTIONS] <TYPE> <INPUT>",
-    arg_required_else_help = true
+    override_usage = "archiver [OPTIONS] <TYPE> <INPUT>\n    archiver <SUBCOMMAND>",
+    arg_required_else_help = true,
+    args_conflicts_with_subcommands = true
 )]
+pub(crate) struct Cli {
+    #[clap(subcommand)]
+    pub(crate) command: Option<Commands>,
+
+    #[clap(flatten)]
+    pub(crate) options: Options,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub(crate) enum Commands {
+    /// Poll channels and archive new videos as they appear
+    #[clap(override_usage = "archiver watch [OPTIONS] <TYPE> --channel <CHANNELS>")]
+    Watch(Watch),
+    /// Work with archived chat files
+    Chat(Chat),
+    /// Search the chat of every archived video for messages matching a pattern
+    SearchChat(SearchChat),
+    /// Re-fetch the metadata of archived videos, keeping the earlier revisions
+    Refresh(Refresh),
+}
+
+#[derive(clap::Args)]
+pub(crate) struct Watch {
+    /// How often to poll the channels, refer to docs for format
+    #[clap(long, short = 'e', default_value = "15m", value_name = "DURATION")]
+    pub(crate) every: String,
+
+    /// Also archive the videos already listed on the first poll, instead of only new ones
+    #[clap(long, takes_value = false)]
+    pub(crate) backfill: bool,
+
+    #[clap(flatten)]
+    pub(crate) options: Options,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct Chat {
+    #[clap(subcommand)]
+    pub(crate) command: ChatCommands,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum ChatCommands {
+    /// Render chat JSON files into subtitles and text logs
+    Render(Render),
+    /// Render chat JSON files into browsable HTML pages
+    Html(Html),
+    /// Count messages, chatters, and emotes, and find spikes in chat activity
+    Stats(Stats),
+}
+
+#[derive(clap::Args)]
+pub(crate) struct Render {
+    /// Chat JSON files from TwitchDownloaderCLI, chat_downloader, or archiver
+    #[clap(required = true, value_name = "FILES")]
+    pub(crate) files: Vec<PathBuf>,
+
+    /// Formats to render, defaults to the config file
+    #[clap(
+        long,
+        short,
+        value_delimiter = ',',
+        possible_values = FORMATS,
+        value_name = "FORMATS"
+    )]
+    pub(crate) formats: Vec<String>,
+
+    /// Directory to write into, defaults to the directory of each file
+    #[clap(long, short, value_name = "DIR")]
+    pub(crate) output_dir: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct Html {
+    /// Chat JSON files, with the video's JSON and emotes read from beside them
+    #[clap(required = true, value_name = "FILES")]
+    pub(crate) files: Vec<PathBuf>,
+
+    /// Directory to write into, defaults to the directory of each file
+    #[clap(long, short, value_name = "DIR")]
+    pub(crate) output_dir: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct Stats {
+    /// Chat JSON files, with the video's emotes read from beside them
+    #[clap(required = true, value_name = "FILES")]
+    pub(crate) files: Vec<PathBuf>,
+
+    /// Number of top chatters and emotes to list
+    #[clap(long, short, default_value = "10", value_name = "COUNT")]
+    pub(crate) top: usize,
+
+    /// Standard deviations above the average messages per minute that start a spike
+    #[clap(long, default_value = "3.0", value_name = "DEVIATIONS")]
+    pub(crate) threshold: f64,
+
+    /// Add the spikes as chapters to the video's JSON beside each file
+    #[clap(long, takes_value = false)]
+    pub(crate) chapters: bool,
+
+    /// Directory to write the JSON into, defaults to the directory of each file
+    #[clap(long, short, value_name = "DIR")]
+    pub(crate) output_dir: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct SearchChat {
+    /// Regular expression to look for in messages
+    #[clap(value_name = "PATTERN")]
+    pub(crate) pattern: String,
+
+    /// Archive directories to search recursively
+    #[clap(default_value = ".", value_name = "DIRS")]
+    pub(crate) dirs: Vec<PathBuf>,
+
+    /// Match the pattern regardless of case
+    #[clap(long, short, takes_value = false)]
+    pub(crate) ignore_case: bool,
+
+    /// Comma separated users to only show messages from
+    #[clap(long, short, value_delimiter = ',', value_name = "USERS")]
+    pub(crate) users: Vec<String>,
+
+    /// Only search videos created on or after this date, as YYYY-MM-DD
+    #[clap(long, value_name = "DATE")]
+    pub(crate) after: Option<String>,
+
+    /// Only search videos created on or before this date, as YYYY-MM-DD
+    #[clap(long, value_name = "DATE")]
+    pub(crate) before: Option<String>,
+
+    /// Print a yt-dlp command downloading the sections around the matches of each video
+    #[clap(long, takes_value = false)]
+    pub(crate) sections: bool,
+
+    /// Seconds to keep before and after each match in sections
+    #[clap(long, default_value = "30", value_name = "SECONDS")]
+    pub(crate) padding: u32,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct Refresh {
+    /// Archive directories to search recursively for video JSON files
+    #[clap(default_value = ".", value_name = "DIRS")]
+    pub(crate) dirs: Vec<PathBuf>,
+
+    /// Number of times to retry failed requests, overrides the config file
+    #[clap(long, short = 'R', value_name = "RETRIES")]
+    pub(crate) retries: Option<u32>,
+
+    /// Increase output verbosity
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    pub(crate) verbose: u8,
+
+    /// Hide output, use twice to hide errors as well
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    pub(crate) silent: u8,
+
+    /// Hide spinners
+    #[clap(short = 'q', long, takes_value = false)]
+    pub(crate) hide_spinners: bool,
+}
+
+/// Chat formats that can be rendered.
+const FORMATS: [&str; 4] = ["ssa", "srt", "vtt", "txt"];
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(clap::Args)]
 #[clap(group(
   ArgGroup::new("type")
     .requires("input")
@@ -21,7 +192,7 @@ use clap::{ArgGroup, Parser};
     .conflicts_with("generate")
     .args(&["channel", "videos"])
 ))]
-pub(crate) struct Cli {
+pub(crate) struct Options {
     /// Twitch VODs
     #[clap(long, help_heading = "TYPE")]
     pub(crate) vods: bool,
@@ -38,7 +209,16 @@ pub(crate) struct Cli {
     #[clap(long, help_heading = "TYPE")]
     pub(crate) youtube: bool,
 
-    /// Target channel (YouTube or Twitch)
+    /// Record live streams of a channel, use with --vods for Twitch or --youtube
+    #[clap(
+        long,
+        help_heading = "TYPE",
+        requires = "channel",
+        conflicts_with_all = &["highlights", "clips", "skip-video"]
+    )]
+    pub(crate) live: bool,
+
+    /// Target channel (YouTube or Twitch), a comma separated list when watching
     #[clap(long, short, help_heading = "INPUT")]
     pub(crate) channel: Option<String>,
 
@@ -54,6 +234,18 @@ pub(crate) struct Cli {
     #[clap(long, short = 'N', default_value = "1")]
     pub(crate) threads: u16,
 
+    /// Number of times to retry failed requests and commands, overrides the config file
+    #[clap(long, short = 'R', value_name = "RETRIES")]
+    pub(crate) retries: Option<u32>,
+
+    /// Number of videos to archive simultaneously
+    #[clap(long, short = 'j', default_value = "1", value_name = "JOBS")]
+    pub(crate) jobs: u16,
+
+    /// Number of videos to download simultaneously when archiving with multiple jobs
+    #[clap(long, short = 'J', default_value = "1", value_name = "JOBS")]
+    pub(crate) video_jobs: u16,
+
     /// How long ago to start searching for clips, refer to docs for format
     #[clap(
         short,
@@ -74,6 +266,95 @@ pub(crate) struct Cli {
     )]
     pub(crate) interval: String,
 
+    /// Only archive videos created on or after this date, as YYYY-MM-DD
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "DATE")]
+    pub(crate) after: Option<String>,
+
+    /// Only archive videos created on or before this date, as YYYY-MM-DD
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "DATE")]
+    pub(crate) before: Option<String>,
+
+    /// Only archive videos at least this long, e.g. 1h2m3s or PT1H2M
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "DURATION")]
+    pub(crate) min_duration: Option<String>,
+
+    /// Only archive videos at most this long, e.g. 1h2m3s or PT1H2M
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "DURATION")]
+    pub(crate) max_duration: Option<String>,
+
+    /// Only archive videos whose title matches this regular expression
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "PATTERN")]
+    pub(crate) match_title: Option<String>,
+
+    /// Skip videos whose title matches this regular expression
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "PATTERN")]
+    pub(crate) reject_title: Option<String>,
+
+    /// Only archive videos with at least this many views
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "VIEWS")]
+    pub(crate) min_views: Option<u64>,
+
+    /// Only archive this many of the newest videos that pass the other filters
+    #[clap(long, help_heading = "CHANNEL FILTERS", value_name = "COUNT")]
+    pub(crate) limit: Option<usize>,
+
+    /// Comma separated chat formats to render, overrides the config file
+    #[clap(
+        long,
+        value_delimiter = ',',
+        possible_values = FORMATS,
+        value_name = "FORMATS"
+    )]
+    pub(crate) chat_formats: Vec<String>,
+
+    /// Write a browsable HTML page of every chat, overrides the config file
+    #[clap(long, takes_value = false)]
+    pub(crate) chat_html: bool,
+
+    /// Archive the emotes and badges used in chat, overrides the config file
+    #[clap(long, takes_value = false)]
+    pub(crate) emotes: bool,
+
+    /// Codec to compress chat files with, overrides the config file
+    #[clap(
+        long,
+        possible_values = ["brotli", "zstd", "xz", "gzip", "none"],
+        value_name = "CODEC"
+    )]
+    pub(crate) compression: Option<String>,
+
+    /// Compression level, overrides the config file [default: highest]
+    #[clap(long, value_name = "LEVEL")]
+    pub(crate) compression_level: Option<u32>,
+
+    /// Delete uncompressed chat files after verifying the compressed ones
+    #[clap(long, takes_value = false)]
+    pub(crate) delete_uncompressed: bool,
+
+    /// Mark the muted segments of Twitch VODs in the embedded chapters, implies --remux, overrides the config file
+    #[clap(long, takes_value = false)]
+    pub(crate) embed_muted: bool,
+
+    /// Embed the metadata, thumbnail and chapters into downloaded videos, overrides the config file
+    #[clap(long, takes_value = false)]
+    pub(crate) remux: bool,
+
+    /// Directory template to archive each video into, refer to docs for variables
+    #[clap(short, long, value_name = "TEMPLATE")]
+    pub(crate) output_template: Option<String>,
+
+    /// Rebuild the archive index from existing files instead of downloading
+    #[clap(long, takes_value = false)]
+    pub(crate) reindex: bool,
+
+    /// Write a JSON report of every video and stage outcome to this file
+    #[clap(long, value_name = "PATH")]
+    pub(crate) report: Option<PathBuf>,
+
+    /// Count requested videos that are deleted, private, or otherwise unavailable as failed
+    #[clap(long, takes_value = false)]
+    pub(crate) fail_on_missing: bool,
+
     /// Enable logging of external commands into files
     #[clap(short, long, takes_value = false)]
     pub(crate) logging: bool,
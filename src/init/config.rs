@@ -1,9 +1,16 @@
-use crate::{utils::Spinner, Error};
+use super::endpoints::Endpoints;
+use crate::downloader::chat::Settings;
+use crate::downloader::compress::Compression;
+use crate::downloader::emotes::Emotes;
+use crate::downloader::muted::Muted;
+use crate::downloader::remux::Remux;
+use crate::downloader::retry::Retry;
+use crate::Error;
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-pub(super) fn get(verbosity: i16, spinner: &mut Spinner) -> Result<Config, Error> {
+pub(super) fn get() -> Result<Config, Error> {
     let Some(project_dirs) = ProjectDirs::from("moe", "Assistant", "archiver") else {
         return Err(Error::Config(
             "Could not find project directory".to_string(),
@@ -24,30 +31,38 @@ pub(super) fn get(verbosity: i16, spinner: &mut Spinner) -> Result<Config, Error
                 config_path.display()
             )));
         }
-        Err(Error::Io) => {
+        Err(err @ Error::Io { .. }) => {
             return Err(Error::Config(format!(
                 "Could not open config file: {}",
-                config_path.display()
+                err.describe()
             )));
         }
         Err(err) => {
-            if verbosity >= 3 {
-                spinner.stop();
-                println!("[config] {err}");
-                spinner.start();
-            }
             return Err(Error::Config(format!(
-                "Could not read config file: {}",
-                config_path.display()
+                "Could not read config file {}: {}",
+                config_path.display(),
+                err.describe()
             )));
         }
     };
     Ok(config)
 }
 
+/// Reads the config file without requiring it to exist, for commands that need no tokens.
+pub(super) fn get_or_default() -> Result<Config, Error> {
+    let Some(project_dirs) = ProjectDirs::from("moe", "Assistant", "archiver") else {
+        return Ok(Config::default());
+    };
+    match read_config(&project_dirs.config_dir().join("config.toml")) {
+        Err(Error::ConfigFileMissing) => Ok(Config::default()),
+        result => result,
+    }
+}
+
 fn read_config(config_path: &PathBuf) -> Result<Config, Error> {
     if config_path.exists() {
-        let config_string = std::fs::read_to_string(config_path)?;
+        let config_string =
+            std::fs::read_to_string(config_path).map_err(|err| Error::io(err, config_path))?;
         let config: Config = toml::from_str(&config_string)?;
         Ok(config)
     } else {
@@ -89,4 +104,20 @@ pub(super) struct Config {
     pub(super) twitch_secret: String,
     #[serde(default)]
     pub(super) youtube_key: String,
+    #[serde(default)]
+    pub(super) output_template: String,
+    #[serde(default)]
+    pub(super) retry: Retry,
+    #[serde(default)]
+    pub(super) endpoints: Endpoints,
+    #[serde(default)]
+    pub(super) chat: Settings,
+    #[serde(default)]
+    pub(super) compression: Compression,
+    #[serde(default)]
+    pub(super) emotes: Emotes,
+    #[serde(default)]
+    pub(super) muted: Muted,
+    #[serde(default)]
+    pub(super) remux: Remux,
 }
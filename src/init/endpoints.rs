@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct Endpoints {
+    pub(crate) twitch_gql: String,
+    pub(crate) twitch_helix: String,
+    /// Twitch emote images, followed by `/<id>/default/dark/2.0`
+    pub(crate) twitch_emotes: String,
+    pub(crate) bttv: String,
+    /// BetterTTV emote images, followed by `/emote/<id>/2x`
+    pub(crate) bttv_cdn: String,
+    pub(crate) ffz: String,
+    pub(crate) seventv: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            twitch_gql: "https://gql.twitch.tv/gql".to_string(),
+            twitch_helix: "https://api.twitch.tv/helix".to_string(),
+            twitch_emotes: "https://static-cdn.jtvnw.net/emoticons/v2".to_string(),
+            bttv: "https://api.betterttv.net/3".to_string(),
+            bttv_cdn: "https://cdn.betterttv.net".to_string(),
+            ffz: "https://api.frankerfacez.com/v1".to_string(),
+            seventv: "https://7tv.io/v3".to_string(),
+        }
+    }
+}
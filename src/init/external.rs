@@ -5,35 +5,33 @@ use which::which;
 
 pub(super) fn find_missing(video_type: &VideoType) -> Vec<External> {
     let commands = match video_type {
-        VideoType::Vod | VideoType::Highlight => {
-            vec![External::Tcd, External::YtDlp, External::TdCli]
-        }
+        VideoType::Vod | VideoType::Highlight => vec![External::YtDlp],
         VideoType::Clip => vec![External::TdCli, External::YtDlp],
-        VideoType::YouTube => vec![External::Cd, External::YtDlp],
+        VideoType::YouTube | VideoType::TwitchLive | VideoType::YouTubeLive => {
+            vec![External::Cd, External::YtDlp]
+        }
     };
     commands.into_iter().filter(|c| !c.is_installed()).collect()
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum External {
-    Tcd,
     YtDlp,
-    Brotli,
     Cd,
     TdCli,
+    Ffmpeg,
 }
 
 impl External {
     pub(crate) fn command(&self) -> &str {
         match self {
-            External::Tcd => "tcd",
             External::YtDlp => "yt-dlp",
-            External::Brotli => "brotli",
             External::Cd => "chat_downloader",
             External::TdCli => "TwitchDownloaderCLI",
+            External::Ffmpeg => "ffmpeg",
         }
     }
-    fn is_installed(&self) -> bool {
+    pub(super) fn is_installed(&self) -> bool {
         which(self.command()).is_ok()
     }
     pub(super) fn missing(&self) {
@@ -44,12 +42,7 @@ impl External {
 impl Display for External {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            External::Tcd => write!(
-                f,
-                "TwitchChatDownloader: https://github.com/TheDrHax/Twitch-Chat-Downloader"
-            ),
             External::YtDlp => write!(f, "yt-dlp: https://github.com/yt-dlp/yt-dlp"),
-            External::Brotli => write!(f, "brotli: https://github.com/google/brotli"),
             External::Cd => write!(
                 f,
                 "chat_downloader: https://github.com/xenova/chat-downloader"
@@ -58,6 +51,7 @@ impl Display for External {
                 f,
                 "TwitchDownloaderCLI: https://github.com/lay295/TwitchDownloader"
             ),
+            External::Ffmpeg => write!(f, "ffmpeg: https://ffmpeg.org"),
         }
     }
 }
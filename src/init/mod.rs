@@ -1,13 +1,31 @@
+use self::args::Parsed;
+use self::cli::{Chat, ChatCommands, Refresh, SearchChat};
+use self::endpoints::Endpoints;
 use self::external::External;
-use crate::{utils::Spinner, Error};
-use chrono::Duration;
+use crate::downloader::chapters;
+use crate::downloader::chat::{Format, Settings};
+use crate::downloader::common::OUTPUT_VARIABLE;
+use crate::downloader::compress::Compression;
+use crate::downloader::emotes::Emotes;
+use crate::downloader::filter::Filter;
+use crate::downloader::index::{Index, INDEX_FILE};
+use crate::downloader::limit::RateLimit;
+use crate::downloader::muted::Muted;
+use crate::downloader::remux::Remux;
+use crate::downloader::report::Report;
+use crate::downloader::retry::Retry;
+use crate::utils::{lock, Semaphore, Spinner};
+use crate::Error;
+use chrono::{Duration, NaiveDate};
 use derive_more::Constructor;
 use fancy_regex::Regex;
 use reqwest::blocking::Client;
-use std::sync::LazyLock;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex, MutexGuard};
 mod args;
 mod cli;
 mod config;
+pub(super) mod endpoints;
 pub(super) mod external;
 mod token;
 
@@ -17,18 +35,94 @@ static PAIR: LazyLock<Regex> =
     LazyLock::new(|| unsafe { Regex::new(r"([0-9]+)([a-zA-Z]+)").unwrap_unchecked() });
 static ZERO: LazyLock<Duration> = LazyLock::new(Duration::zero);
 
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub(super) enum Task {
+    Archive(Input),
+    Chat(ChatTask),
+    Search(SearchTask),
+    Refresh(RefreshTask),
+}
+
 #[derive(Debug, Constructor)]
 pub(super) struct Input {
     pub(super) videos: Videos,
     pub(super) context: Context,
+    pub(super) watch: Option<Duration>,
+    /// Whether watching archives the videos listed on the first poll
+    pub(super) backfill: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct ChatTask {
+    pub(crate) command: ChatCommand,
+    pub(crate) settings: Settings,
 }
 
-pub(super) fn run() -> Result<Input, Error> {
-    let args = args::parse();
-    let mut spinner = Spinner::new(args.verbosity, args.hide_spinners);
+#[derive(Debug)]
+pub(crate) struct SearchTask {
+    pub(crate) pattern: Regex,
+    pub(crate) dirs: Vec<PathBuf>,
+    /// Lowercase names of the users to show messages from, all when empty
+    pub(crate) users: Vec<String>,
+    pub(crate) after: Option<NaiveDate>,
+    pub(crate) before: Option<NaiveDate>,
+    pub(crate) sections: bool,
+    pub(crate) padding: u32,
+}
+
+#[derive(Debug)]
+pub(crate) struct RefreshTask {
+    pub(crate) dirs: Vec<PathBuf>,
+    pub(crate) verbosity: i16,
+    /// Context of the Twitch lookups, or why there is none
+    pub(crate) twitch: Result<Context, Error>,
+    /// Context of the YouTube lookups, or why there is none
+    pub(crate) youtube: Result<Context, Error>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ChatCommand {
+    Render {
+        files: Vec<PathBuf>,
+        formats: Vec<Format>,
+        output_dir: Option<PathBuf>,
+    },
+    Html {
+        files: Vec<PathBuf>,
+        output_dir: Option<PathBuf>,
+    },
+    Stats {
+        files: Vec<PathBuf>,
+        top: usize,
+        threshold: f64,
+        chapters: bool,
+        output_dir: Option<PathBuf>,
+    },
+}
+
+pub(super) fn run() -> Result<Task, Error> {
+    let args = match args::parse() {
+        Parsed::Archive(args) => args,
+        Parsed::Chat(command) => return chat(command).map(Task::Chat),
+        Parsed::SearchChat(search) => return search_chat(search).map(Task::Search),
+        Parsed::Refresh(refresh) => return self::refresh(refresh).map(Task::Refresh),
+    };
+    let watch = match args.every {
+        Some(every) => match parse_duration(&every) {
+            Some(duration) if duration > *ZERO => Some(duration),
+            _ => {
+                return Err(Error::Config(format!(
+                    "Invalid --every duration, expected e.g. 15m or 1h30m: {every}"
+                )))
+            }
+        },
+        None => None,
+    };
+    let spinner = Spinner::new(args.verbosity, args.hide_spinners);
 
     spinner.create(" Checking external programs");
-    let missing = external::find_missing(&args.video_type);
+    let mut missing = external::find_missing(&args.video_type);
     spinner.end();
     if args.verbosity >= -1 {
         for command in &missing {
@@ -37,7 +131,7 @@ pub(super) fn run() -> Result<Input, Error> {
     };
 
     spinner.create(" Getting config");
-    let config = match config::get(args.verbosity, &mut spinner) {
+    let config = match config::get() {
         Ok(config) => config,
         Err(error) => {
             spinner.end();
@@ -56,17 +150,85 @@ pub(super) fn run() -> Result<Input, Error> {
     };
     spinner.end();
 
+    let output_template = args.output_template.unwrap_or(config.output_template);
+    let retry = Retry {
+        attempts: args
+            .retries
+            .map_or(config.retry.attempts, |retries| retries + 1),
+        ..config.retry
+    };
+    check_template(&output_template)?;
+    let chat = Settings {
+        formats: match args.chat_formats.is_empty() {
+            true => config.chat.formats,
+            false => parse_formats(&args.chat_formats)?,
+        },
+        html: args.chat_html || config.chat.html,
+        ..config.chat
+    };
+    let emotes = Emotes {
+        enabled: args.emotes || config.emotes.enabled,
+        ..config.emotes
+    };
+    let compression = Compression {
+        codec: match &args.compression {
+            Some(codec) => codec.parse()?,
+            None => config.compression.codec,
+        },
+        level: args.compression_level.or(config.compression.level),
+        delete_original: args.delete_uncompressed || config.compression.delete_original,
+    };
+    let muted = Muted {
+        embed: args.embed_muted || config.muted.embed,
+    };
+    let remux = Remux {
+        enabled: args.remux || config.remux.enabled || muted.embed,
+    };
+    if remux.enabled && !External::Ffmpeg.is_installed() {
+        if args.verbosity >= -1 {
+            External::Ffmpeg.missing();
+        }
+        missing.push(External::Ffmpeg);
+    }
+
+    spinner.create(" Loading archive index");
+    let index = match Index::load(INDEX_FILE) {
+        Ok(index) => index,
+        Err(error) => {
+            spinner.end();
+            return Err(Error::Index(format!(
+                "Could not read archive index {INDEX_FILE}: {error}"
+            )));
+        }
+    };
+    spinner.end();
+
     let (range, interval) = match args.video_type {
         VideoType::Clip => {
             spinner.create(" Parsing arguments");
-            let range = parse_duration(&args.range);
-            let interval = parse_duration(&args.interval);
+            let durations = parse_duration(&args.range).zip(parse_duration(&args.interval));
             spinner.end();
-            (range, interval)
+            durations.ok_or_else(|| {
+                Error::Config(format!(
+                    "Invalid --range or --interval duration, expected e.g. 1week or 1hour: {} {}",
+                    args.range, args.interval
+                ))
+            })?
         }
         _ => (*ZERO, *ZERO),
     };
 
+    let filter = Filter {
+        after: parse_date(args.after)?,
+        before: parse_date(args.before)?,
+        min_duration: parse_seconds(args.min_duration)?,
+        max_duration: parse_seconds(args.max_duration)?,
+        match_title: parse_pattern(args.match_title)?,
+        reject_title: parse_pattern(args.reject_title)?,
+        min_views: args.min_views,
+        limit: args.limit,
+    };
+
     let context = Context {
         verbosity: args.verbosity,
         token: token_package.token,
@@ -74,10 +236,27 @@ pub(super) fn run() -> Result<Input, Error> {
         client_id: token_package.client_id,
         downloader: args.video_type,
         skip_video: args.skip_video,
+        output_template,
+        reindex: args.reindex,
+        index: Mutex::new(index),
+        report: Mutex::new(Report::default()),
+        report_path: args.report,
+        fail_on_missing: args.fail_on_missing,
+        retry,
+        rate_limit: RateLimit::default(),
+        endpoints: config.endpoints,
+        chat,
+        compression,
+        emotes,
+        muted,
+        remux,
         threads: args.threads,
+        jobs: usize::from(args.jobs.max(1)),
+        video_jobs: Semaphore::new(usize::from(args.video_jobs.max(1))),
         missing,
         range,
         interval,
+        filter,
         logging: args.logging,
         spinner,
         post_json: args.post_json,
@@ -86,7 +265,155 @@ pub(super) fn run() -> Result<Input, Error> {
         post_chat_process: args.post_chat_process,
         post_video: args.post_video,
     };
-    Ok(Input::new(args.videos, context))
+    Ok(Task::Archive(Input::new(
+        args.videos,
+        context,
+        watch,
+        args.backfill,
+    )))
+}
+
+fn chat(chat: Chat) -> Result<ChatTask, Error> {
+    let settings = config::get_or_default()?.chat;
+    let command = match chat.command {
+        ChatCommands::Render(render) => ChatCommand::Render {
+            formats: match render.formats.is_empty() {
+                true => settings.formats.clone(),
+                false => parse_formats(&render.formats)?,
+            },
+            files: render.files,
+            output_dir: render.output_dir,
+        },
+        ChatCommands::Html(html) => ChatCommand::Html {
+            files: html.files,
+            output_dir: html.output_dir,
+        },
+        ChatCommands::Stats(stats) => ChatCommand::Stats {
+            files: stats.files,
+            top: stats.top,
+            threshold: stats.threshold,
+            chapters: stats.chapters,
+            output_dir: stats.output_dir,
+        },
+    };
+    Ok(ChatTask { command, settings })
+}
+
+fn search_chat(search: SearchChat) -> Result<SearchTask, Error> {
+    let pattern = match search.ignore_case {
+        true => format!("(?i){}", search.pattern),
+        false => search.pattern,
+    };
+    Ok(SearchTask {
+        pattern: Regex::new(&pattern)
+            .map_err(|err| Error::Config(format!("Invalid pattern: {err}")))?,
+        dirs: search.dirs,
+        users: search
+            .users
+            .iter()
+            .map(|user| user.to_lowercase())
+            .collect(),
+        after: parse_date(search.after)?,
+        before: parse_date(search.before)?,
+        sections: search.sections,
+        padding: search.padding,
+    })
+}
+
+fn refresh(refresh: Refresh) -> Result<RefreshTask, Error> {
+    let verbosity = i16::from(refresh.verbose) - i16::from(refresh.silent);
+    let config = config::get()?;
+    let index = Index::load(INDEX_FILE).map_err(|error| {
+        Error::Index(format!(
+            "Could not read archive index {INDEX_FILE}: {error}"
+        ))
+    })?;
+    let context = |video_type: VideoType| -> Result<Context, Error> {
+        let token_package = token::get(&video_type, &config)?;
+        Ok(Context {
+            verbosity,
+            token: token_package.token,
+            client: token_package.client,
+            client_id: token_package.client_id,
+            downloader: video_type,
+            skip_video: true,
+            output_template: config.output_template.clone(),
+            reindex: false,
+            index: Mutex::new(index.clone()),
+            report: Mutex::new(Report::default()),
+            report_path: None,
+            fail_on_missing: false,
+            retry: Retry {
+                attempts: refresh
+                    .retries
+                    .map_or(config.retry.attempts, |retries| retries + 1),
+                ..config.retry.clone()
+            },
+            rate_limit: RateLimit::default(),
+            endpoints: config.endpoints.clone(),
+            chat: config.chat.clone(),
+            compression: config.compression.clone(),
+            emotes: config.emotes.clone(),
+            muted: Muted::default(),
+            remux: Remux::default(),
+            threads: 1,
+            jobs: 1,
+            video_jobs: Semaphore::new(1),
+            missing: Vec::new(),
+            range: *ZERO,
+            interval: *ZERO,
+            filter: Filter::default(),
+            logging: false,
+            spinner: Spinner::new(verbosity, refresh.hide_spinners),
+            post_json: Vec::new(),
+            post_thumbnail: Vec::new(),
+            post_chat: Vec::new(),
+            post_chat_process: Vec::new(),
+            post_video: Vec::new(),
+        })
+    };
+    let (twitch, youtube) = (context(VideoType::Vod), context(VideoType::YouTube));
+    if let (Err(error), Err(_)) = (&twitch, &youtube) {
+        return Err(Error::Token(format!(
+            "No Twitch or YouTube credentials to refresh with: {}",
+            error.describe()
+        )));
+    }
+    Ok(RefreshTask {
+        dirs: refresh.dirs,
+        verbosity,
+        twitch,
+        youtube,
+    })
+}
+
+fn parse_date(date: Option<String>) -> Result<Option<NaiveDate>, Error> {
+    date.map(|date| {
+        NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|_| Error::Config(format!("Invalid date, expected YYYY-MM-DD: {date}")))
+    })
+    .transpose()
+}
+
+fn parse_seconds(duration: Option<String>) -> Result<Option<f64>, Error> {
+    duration
+        .map(|duration| {
+            chapters::seconds(&duration)
+                .ok_or_else(|| Error::Config(format!("Invalid duration: {duration}")))
+        })
+        .transpose()
+}
+
+fn parse_pattern(pattern: Option<String>) -> Result<Option<Regex>, Error> {
+    pattern
+        .map(|pattern| {
+            Regex::new(&pattern).map_err(|err| Error::Config(format!("Invalid pattern: {err}")))
+        })
+        .transpose()
+}
+
+fn parse_formats(formats: &[String]) -> Result<Vec<Format>, Error> {
+    formats.iter().map(|format| format.parse()).collect()
 }
 
 #[derive(Debug)]
@@ -107,6 +434,8 @@ pub(super) enum VideoType {
     Highlight,
     Clip,
     YouTube,
+    TwitchLive,
+    YouTubeLive,
 }
 
 #[derive(Debug)]
@@ -117,10 +446,27 @@ pub(super) struct Context {
     pub(super) client_id: String,
     pub(super) downloader: VideoType,
     pub(super) skip_video: bool,
+    pub(super) output_template: String,
+    pub(super) reindex: bool,
+    pub(super) index: Mutex<Index>,
+    pub(super) report: Mutex<Report>,
+    pub(super) report_path: Option<PathBuf>,
+    pub(super) fail_on_missing: bool,
+    pub(super) retry: Retry,
+    pub(super) rate_limit: RateLimit,
+    pub(super) endpoints: Endpoints,
+    pub(super) chat: Settings,
+    pub(super) compression: Compression,
+    pub(super) emotes: Emotes,
+    pub(super) muted: Muted,
+    pub(super) remux: Remux,
     pub(super) threads: u16,
+    pub(super) jobs: usize,
+    pub(super) video_jobs: Semaphore,
     pub(super) missing: Vec<External>,
     pub(super) range: Duration,
     pub(super) interval: Duration,
+    pub(super) filter: Filter,
     pub(super) logging: bool,
     pub(super) spinner: Spinner,
     pub(super) post_json: Vec<String>,
@@ -130,7 +476,89 @@ pub(super) struct Context {
     pub(super) post_video: Vec<String>,
 }
 
-fn parse_duration(text: &str) -> Duration {
+impl Context {
+    pub(super) fn index(&self) -> MutexGuard<'_, Index> {
+        lock(&self.index)
+    }
+    pub(super) fn report(&self) -> MutexGuard<'_, Report> {
+        lock(&self.report)
+    }
+    /// Quiet context for tests archiving every video into `dir`, with an index
+    /// there that is only written on save.
+    #[cfg(test)]
+    pub(super) fn test(endpoints: Endpoints, dir: &std::path::Path) -> Self {
+        Self {
+            verbosity: -2,
+            token: String::new(),
+            client: Client::new(),
+            client_id: String::new(),
+            downloader: VideoType::Vod,
+            skip_video: true,
+            output_template: dir.to_string_lossy().into_owned(),
+            reindex: false,
+            index: Mutex::new(Index::load(dir.join(INDEX_FILE)).unwrap()),
+            report: Mutex::new(Report::default()),
+            report_path: None,
+            fail_on_missing: false,
+            // A request that was not expected fails the test at once
+            retry: Retry {
+                attempts: 1,
+                ..Retry::default()
+            },
+            rate_limit: RateLimit::default(),
+            endpoints,
+            chat: Settings::default(),
+            compression: Compression::default(),
+            emotes: Emotes::default(),
+            muted: Muted::default(),
+            remux: Remux::default(),
+            threads: 1,
+            jobs: 1,
+            video_jobs: Semaphore::new(1),
+            missing: Vec::new(),
+            range: Duration::zero(),
+            interval: Duration::zero(),
+            filter: Filter::default(),
+            logging: false,
+            spinner: Spinner::new(-2, true),
+            post_json: Vec::new(),
+            post_thumbnail: Vec::new(),
+            post_chat: Vec::new(),
+            post_chat_process: Vec::new(),
+            post_video: Vec::new(),
+        }
+    }
+}
+
+fn check_template(template: &str) -> Result<(), Error> {
+    const VARIABLES: [&str; 10] = [
+        "platform",
+        "type",
+        "id",
+        "title",
+        "user_id",
+        "user_login",
+        "user_name",
+        "language",
+        "created_at",
+        "published_at",
+    ];
+    for captures in OUTPUT_VARIABLE.captures_iter(template) {
+        let Some(name) = captures.ok().and_then(|captures| captures.get(1)) else {
+            continue;
+        };
+        if !VARIABLES.contains(&name.as_str()) {
+            return Err(Error::Config(format!(
+                "Unknown variable in output template: {{{}}}",
+                name.as_str()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Sum of the `<number><unit>` pairs in `text`, none when it does not fit in a `Duration`.
+fn parse_duration(text: &str) -> Option<Duration> {
     let mut duration = Duration::seconds(0);
     let result = SPLIT.captures_iter(text);
     let pairs = result
@@ -139,21 +567,22 @@ fn parse_duration(text: &str) -> Duration {
         .collect::<Vec<Time>>();
     for pair in pairs {
         let Time { number, unit } = pair;
-        let number = number.parse::<i64>().unwrap();
-        match unit.to_lowercase().as_str() {
-            "s" | "second" | "seconds" => add(&mut duration, Duration::seconds(number)),
-            "m" | "minute" | "minutes" => add(&mut duration, Duration::minutes(number)),
-            "h" | "hour" | "hours" => add(&mut duration, Duration::hours(number)),
-            "d" | "day" | "days" => add(&mut duration, Duration::days(number)),
-            "w" | "week" | "weeks" => add(&mut duration, Duration::weeks(number)),
-            _ => {}
-        }
+        let seconds: i64 = match unit.to_lowercase().as_str() {
+            "s" | "second" | "seconds" => 1,
+            "m" | "minute" | "minutes" => 60,
+            "h" | "hour" | "hours" => 3_600,
+            "d" | "day" | "days" => 86_400,
+            "w" | "week" | "weeks" => 604_800,
+            _ => continue,
+        };
+        let milliseconds = number
+            .parse::<i64>()
+            .ok()?
+            .checked_mul(seconds)?
+            .checked_mul(1000)?;
+        duration = duration.checked_add(&Duration::milliseconds(milliseconds))?;
     }
-    duration
-}
-
-fn add(a: &mut Duration, b: Duration) {
-    *a = *a + b;
+    Some(duration)
 }
 
 #[derive(Debug)]
@@ -170,3 +599,53 @@ impl From<&str> for Time {
         Time { number, unit }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_variables() {
+        for template in [
+            ".",
+            "/archive/{platform}/{user_login}/{created_at:%Y/%m}/{title} [{id}]",
+            "{type}-{user_id}-{user_name}-{language}-{published_at:%F}",
+            // Not a variable
+            "{Title}",
+        ] {
+            assert!(check_template(template).is_ok(), "{template}");
+        }
+        for (template, variable) in [
+            ("/archive/{channel}", "{channel}"),
+            ("{id}/{date:%Y}", "{date}"),
+            ("{title}{user_login}{views}", "{views}"),
+        ] {
+            match check_template(template) {
+                Err(Error::Config(message)) => {
+                    assert_eq!(
+                        message,
+                        format!("Unknown variable in output template: {variable}")
+                    );
+                }
+                result => panic!("{template}: {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let cases = [
+            ("15m", Some(Duration::minutes(15))),
+            ("1h30m", Some(Duration::minutes(90))),
+            ("1week", Some(Duration::weeks(1))),
+            ("2days1second", Some(Duration::seconds(172_801))),
+            ("5parsecs", Some(Duration::zero())),
+            ("99999999999999999999h", None),
+            ("9999999999999999h", None),
+            ("2562047788015h2562047788015h", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_duration(text), expected, "{text}");
+        }
+    }
+}
@@ -19,8 +19,10 @@ pub(super) struct TokenPackage {
 
 pub(super) fn get(video_type: &VideoType, config: &Config) -> Result<TokenPackage, Error> {
     match video_type {
-        VideoType::Vod | VideoType::Highlight | VideoType::Clip => get_twitch_token(config),
-        VideoType::YouTube => get_youtube_token(config),
+        VideoType::Vod | VideoType::Highlight | VideoType::Clip | VideoType::TwitchLive => {
+            get_twitch_token(config)
+        }
+        VideoType::YouTube | VideoType::YouTubeLive => get_youtube_token(config),
     }
 }
 
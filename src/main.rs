@@ -2,23 +2,49 @@ mod downloader;
 mod init;
 use downloader::clip::Clip;
 pub(crate) use downloader::error::Error;
+use downloader::report;
 use downloader::twitch::Video;
 pub(crate) use downloader::utils;
 use downloader::youtube::YtVideo;
-use init::{Input, VideoType};
+use init::{Input, Task, VideoType};
+use std::process::ExitCode;
 use utils::error;
 
-fn main() {
-    match init::run() {
-        Ok(Input {
+fn main() -> ExitCode {
+    let code = match init::run() {
+        Ok(Task::Archive(Input {
             videos,
-            mut context,
-        }) => match &context.downloader {
-            VideoType::Vod | VideoType::Highlight => videos.download::<Video>(&mut context),
-            VideoType::Clip => videos.download::<Clip>(&mut context),
-            VideoType::YouTube => videos.download::<YtVideo>(&mut context),
+            context,
+            watch: Some(every),
+            backfill,
+        })) => match &context.downloader {
+            VideoType::Vod | VideoType::Highlight | VideoType::TwitchLive => {
+                videos.watch::<Video>(every, backfill, &context)
+            }
+            VideoType::Clip => videos.watch::<Clip>(every, backfill, &context),
+            VideoType::YouTube | VideoType::YouTubeLive => {
+                videos.watch::<YtVideo>(every, backfill, &context)
+            }
         },
-        Err(Error::Token(msg) | Error::Config(msg)) => error(&msg, None),
-        Err(_) => {}
-    }
+        Ok(Task::Archive(Input {
+            videos,
+            context,
+            watch: None,
+            ..
+        })) => match &context.downloader {
+            VideoType::Vod | VideoType::Highlight | VideoType::TwitchLive => {
+                videos.download::<Video>(&context)
+            }
+            VideoType::Clip => videos.download::<Clip>(&context),
+            VideoType::YouTube | VideoType::YouTubeLive => videos.download::<YtVideo>(&context),
+        },
+        Ok(Task::Chat(task)) => task.run(),
+        Ok(Task::Search(task)) => task.run(),
+        Ok(Task::Refresh(task)) => task.run(),
+        Err(err) => {
+            error(&err.describe(), None);
+            report::CONFIG_ERROR
+        }
+    };
+    ExitCode::from(code)
 }